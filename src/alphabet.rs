@@ -0,0 +1,139 @@
+use std::char;
+
+use super::NFA;
+use super::class::{nth_from, pred, range_size, succ};
+
+/// A partition of all of `char` into ranges of characters that no
+/// transition of the automata it was built from can tell apart. Automata
+/// over an alphabet only need one transition per range, so a `[a-z]` edge
+/// costs one table entry rather than 26.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Alphabet {
+    /// The first character of each class, sorted and starting with '\0'
+    starts: Vec<char>,
+}
+
+impl Alphabet {
+
+    pub fn from_nfa(nfa: &NFA) -> Alphabet {
+        Self::from_nfas(&[nfa])
+    }
+
+    /// The coarsest partition refining the transition labels of every NFA
+    pub fn from_nfas(nfas: &[&NFA]) -> Alphabet {
+        let mut starts = vec!['\0'];
+        for nfa in nfas {
+            for n in nfa.nodes.iter() {
+                for &(label, _) in n.transitions.iter() {
+                    if let Some((lo, hi)) = label {
+                        starts.push(lo);
+                        if let Some(c) = succ(hi) {
+                            starts.push(c);
+                        }
+                    }
+                }
+            }
+        }
+        starts.sort();
+        starts.dedup();
+        Alphabet { starts: starts }
+    }
+
+    /// The coarsest partition refining both alphabets
+    pub fn refine(&self, other: &Alphabet) -> Alphabet {
+        let mut starts = self.starts.clone();
+        starts.extend(other.starts.iter().cloned());
+        starts.sort();
+        starts.dedup();
+        Alphabet { starts: starts }
+    }
+
+    /// The number of classes
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// The index of the class containing `c`
+    pub fn class_of(&self, c: char) -> usize {
+        match self.starts.binary_search(&c) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        }
+    }
+
+    /// The inclusive range of characters in class `i`
+    pub fn range(&self, i: usize) -> (char, char) {
+        let hi = match self.starts.get(i + 1) {
+            Some(&next) => pred(next).unwrap(),
+            None => char::MAX,
+        };
+        (self.starts[i], hi)
+    }
+
+    /// The number of characters in class `i`, which leaves out the
+    /// surrogate code points that are not characters
+    pub fn size(&self, i: usize) -> u32 {
+        let (lo, hi) = self.range(i);
+        range_size(lo, hi)
+    }
+
+    /// The character `k` places into class `i`, for `k` below its size
+    pub fn nth(&self, i: usize, k: u32) -> char {
+        nth_from(self.range(i).0, k)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::char;
+    use super::Alphabet;
+    use super::super::{NFA, Regex};
+
+    #[test]
+    fn test_alphabet_classes() {
+        let r = Regex::class(&[('a', 'z')], false).or(&Regex::Single('m'));
+        let alphabet = Alphabet::from_nfa(&NFA::from_regex(&r));
+
+        assert_eq!(alphabet.len(), 5);
+        assert_eq!(alphabet.range(0), ('\0', '`'));
+        assert_eq!(alphabet.range(1), ('a', 'l'));
+        assert_eq!(alphabet.range(2), ('m', 'm'));
+        assert_eq!(alphabet.range(3), ('n', 'z'));
+        assert_eq!(alphabet.range(4), ('{', char::MAX));
+        assert_eq!(alphabet.class_of('\0'), 0);
+        assert_eq!(alphabet.class_of('a'), 1);
+        assert_eq!(alphabet.class_of('c'), 1);
+        assert_eq!(alphabet.class_of('m'), 2);
+        assert_eq!(alphabet.class_of('z'), 3);
+        assert_eq!(alphabet.class_of(char::MAX), 4);
+        assert_eq!(alphabet.size(1), 12);
+        assert_eq!(alphabet.nth(3, 2), 'p');
+    }
+
+    #[test]
+    fn test_alphabet_refine() {
+        let a = Alphabet::from_nfa(&NFA::from_regex(&Regex::class(&[('a', 'm')], false)));
+        let b = Alphabet::from_nfa(&NFA::from_regex(&Regex::class(&[('k', 'z')], false)));
+        let both = a.refine(&b);
+
+        assert_eq!(both, b.refine(&a));
+        assert_eq!(both.len(), 5);
+        assert_eq!(both.range(1), ('a', 'j'));
+        assert_eq!(both.range(2), ('k', 'm'));
+        assert_eq!(both.range(3), ('n', 'z'));
+        assert_eq!(a.refine(&a), a);
+    }
+
+    #[test]
+    fn test_alphabet_full_range() {
+        let alphabet = Alphabet::from_nfa(&NFA::from_regex(&Regex::any()));
+
+        assert_eq!(alphabet.len(), 1);
+        assert_eq!(alphabet.range(0), ('\0', char::MAX));
+        assert_eq!(alphabet.size(0), 0x110000 - 0x800);
+        assert_eq!(alphabet.nth(0, 0xD7FF), '\u{D7FF}');
+        assert_eq!(alphabet.nth(0, 0xD800), '\u{E000}');
+        assert_eq!(alphabet.nth(0, alphabet.size(0) - 1), char::MAX);
+    }
+}
@@ -0,0 +1,76 @@
+//! Timing comparisons between `NFA::accepts` and the original simulation,
+//! which allocated fresh `HashSet`s for every character and recomputed the
+//! epsilon closure by re-stepping until the set stopped growing. These are
+//! ignored by default; run them with
+//!
+//!     rustc -O --test src/main.rs -o bench && ./bench bench --ignored --nocapture
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use super::{NFA, Regex};
+
+/// The simulation `NFA::accepts` used before state sets were sparse sets
+fn reference_accepts(nfa: &NFA, xs: &[char]) -> bool {
+    let mut states = HashSet::new();
+    states.insert(nfa.start_idx);
+    reference_closure(nfa, &mut states);
+
+    for &c in xs.iter() {
+        states = nfa.step(&states, Some(c));
+        if states.is_empty() {
+            return false;
+        }
+        reference_closure(nfa, &mut states);
+    }
+
+    states.iter().any(|&s| nfa.is_accepting(s))
+}
+
+fn reference_closure(nfa: &NFA, states: &mut HashSet<usize>) {
+    let mut size = states.len();
+    loop {
+        let new_nodes = nfa.step(states, None);
+        if new_nodes.is_empty() {
+            break;
+        }
+        for n in new_nodes.into_iter() {
+            states.insert(n);
+        }
+        if states.len() == size {
+            break;
+        }
+        size = states.len();
+    }
+}
+
+fn time<F: FnMut() -> bool>(iterations: usize, mut f: F) -> (Duration, bool) {
+    let start = Instant::now();
+    let mut result = false;
+    for _ in 0..iterations {
+        result = f();
+    }
+    (start.elapsed() / iterations as u32, result)
+}
+
+fn compare(name: &str, pattern: &str, input: &[char], iterations: usize) {
+    let nfa = NFA::from_regex(&Regex::parse(pattern).unwrap());
+    let (old, old_result) = time(iterations, || reference_accepts(&nfa, input));
+    let (new, new_result) = time(iterations, || nfa.accepts(input));
+    assert_eq!(old_result, new_result, "{}", name);
+    println!("{:<24} {:>6} nodes {:>8} chars   hashset {:>12?}   sparse {:>12?}",
+             name, nfa.nodes.len(), input.len(), old, new);
+}
+
+#[test]
+#[ignore]
+fn bench_accepts() {
+    let text: Vec<char> = "the quick brown fox jumps over the lazy dog ".chars().cycle().take(10000).collect();
+    compare("words", "([a-z]+ )*", &text, 10);
+    compare("any", ".*", &text, 10);
+    compare("alternation", "(the|quick|brown|fox|jumps|over|lazy|dog| )*", &text, 10);
+
+    let ab: Vec<char> = "ab".chars().cycle().take(2000).collect();
+    compare("nested stars", "((a*)*(b*)*)*", &ab, 10);
+    compare("bounded", "(a|b)*a(a|b){20}", &ab, 10);
+}
@@ -0,0 +1,234 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Sub};
+
+use super::Rng;
+
+/// An arbitrarily large unsigned integer, with just the operations needed for
+/// counting strings, so as not to depend on an external crate. The digits are
+/// base 2^32 limbs, least significant first, with no zero limbs at the end.
+#[derive(Debug,Clone,PartialEq,Eq,Hash,Default)]
+pub struct BigUint {
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+
+    pub fn zero() -> BigUint {
+        BigUint { limbs: vec![] }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    /// The value, if it fits in a `u64`
+    pub fn to_u64(&self) -> Option<u64> {
+        match self.limbs.len() {
+            0 => Some(0),
+            1 => Some(self.limbs[0] as u64),
+            2 => Some(self.limbs[0] as u64 | (self.limbs[1] as u64) << 32),
+            _ => None,
+        }
+    }
+
+    /// The number of bits needed to write the value
+    pub fn bits(&self) -> usize {
+        match self.limbs.last() {
+            None => 0,
+            Some(&top) => 32 * self.limbs.len() - top.leading_zeros() as usize,
+        }
+    }
+
+    /// A uniformly random number below `self`, which must be positive
+    pub fn random_below(&self, rng: &mut Rng) -> BigUint {
+        assert!(!self.is_zero(), "no number is below zero");
+        let bits = self.bits();
+        // drawing just enough bits succeeds at least half the time
+        loop {
+            let mut limbs: Vec<u32> = (0..self.limbs.len()).map(|_| rng.next_u64() as u32).collect();
+            let spare = 32 * limbs.len() - bits;
+            *limbs.last_mut().unwrap() >>= spare;
+            let r = BigUint::from_limbs(limbs);
+            if r < *self {
+                return r;
+            }
+        }
+    }
+
+    fn from_limbs(mut limbs: Vec<u32>) -> BigUint {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        BigUint { limbs: limbs }
+    }
+
+    /// The quotient and remainder on dividing by `d`, which must be positive
+    fn div_rem_small(&self, d: u32) -> (BigUint, u32) {
+        let mut quotient = vec![0; self.limbs.len()];
+        let mut rem = 0u64;
+        for i in (0..self.limbs.len()).rev() {
+            let cur = rem << 32 | self.limbs[i] as u64;
+            quotient[i] = (cur / d as u64) as u32;
+            rem = cur % d as u64;
+        }
+        (BigUint::from_limbs(quotient), rem as u32)
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(n: u64) -> BigUint {
+        BigUint::from_limbs(vec![n as u32, (n >> 32) as u32])
+    }
+}
+
+impl Add<&BigUint> for &BigUint {
+    type Output = BigUint;
+
+    fn add(self, other: &BigUint) -> BigUint {
+        let mut limbs = Vec::with_capacity(self.limbs.len().max(other.limbs.len()) + 1);
+        let mut carry = 0u64;
+        for i in 0..self.limbs.len().max(other.limbs.len()) {
+            let sum = carry
+                + *self.limbs.get(i).unwrap_or(&0) as u64
+                + *other.limbs.get(i).unwrap_or(&0) as u64;
+            limbs.push(sum as u32);
+            carry = sum >> 32;
+        }
+        limbs.push(carry as u32);
+        BigUint::from_limbs(limbs)
+    }
+}
+
+/// Panics if `other` is the larger
+impl Sub<&BigUint> for &BigUint {
+    type Output = BigUint;
+
+    fn sub(self, other: &BigUint) -> BigUint {
+        assert!(*self >= *other, "subtraction would underflow");
+        let mut limbs = Vec::with_capacity(self.limbs.len());
+        let mut borrow = 0i64;
+        for i in 0..self.limbs.len() {
+            let mut diff = self.limbs[i] as i64 - *other.limbs.get(i).unwrap_or(&0) as i64 - borrow;
+            borrow = 0;
+            if diff < 0 {
+                diff += 1 << 32;
+                borrow = 1;
+            }
+            limbs.push(diff as u32);
+        }
+        BigUint::from_limbs(limbs)
+    }
+}
+
+impl Mul<u32> for &BigUint {
+    type Output = BigUint;
+
+    fn mul(self, m: u32) -> BigUint {
+        let mut limbs = Vec::with_capacity(self.limbs.len() + 1);
+        let mut carry = 0u64;
+        for &limb in self.limbs.iter() {
+            let product = limb as u64 * m as u64 + carry;
+            limbs.push(product as u32);
+            carry = product >> 32;
+        }
+        limbs.push(carry as u32);
+        BigUint::from_limbs(limbs)
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &BigUint) -> Ordering {
+        // without zero limbs at the end, a longer number is a larger one
+        self.limbs.len().cmp(&other.limbs.len())
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &BigUint) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// In decimal
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return f.pad("0");
+        }
+        // nine decimal digits at a time, least significant first
+        let mut chunks = vec![];
+        let mut n = self.clone();
+        while !n.is_zero() {
+            let (q, r) = n.div_rem_small(1_000_000_000);
+            chunks.push(r);
+            n = q;
+        }
+        let mut s = chunks.pop().unwrap().to_string();
+        for chunk in chunks.iter().rev() {
+            s.push_str(&format!("{:09}", chunk));
+        }
+        f.pad(&s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::BigUint;
+    use super::super::Rng;
+
+    #[test]
+    fn test_biguint_arithmetic() {
+        let max = BigUint::from(u64::MAX);
+        let one = BigUint::from(1);
+        let big = &max + &one;
+
+        assert_eq!(big.to_u64(), None);
+        assert_eq!(big.bits(), 65);
+        assert_eq!(big.to_string(), "18446744073709551616");
+        assert_eq!(&big - &one, max);
+        assert_eq!((&big - &max).to_u64(), Some(1));
+        assert_eq!((&max * 3).to_string(), "55340232221128654845");
+        assert!((&big - &big).is_zero());
+        let nothing = (&one - &one).to_u64().unwrap() as u32;
+        assert_eq!(&big * nothing, BigUint::zero());
+        assert!(BigUint::zero() < one && one < max && max < big);
+        assert_eq!(BigUint::zero().to_string(), "0");
+        assert_eq!(BigUint::from(1_000_000_000).to_string(), "1000000000");
+    }
+
+    #[test]
+    fn test_biguint_powers() {
+        // 2^100 and 10^30, built by repeated multiplication
+        let mut two = BigUint::from(1);
+        let mut ten = BigUint::from(1);
+        for _ in 0..100 {
+            two = &two * 2;
+        }
+        for _ in 0..30 {
+            ten = &ten * 10;
+        }
+        assert_eq!(two.to_string(), "1267650600228229401496703205376");
+        assert_eq!(ten.to_string(), format!("1{}", "0".repeat(30)));
+        assert_eq!(two.bits(), 101);
+        assert!(ten < two);
+    }
+
+    #[test]
+    fn test_biguint_random_below() {
+        let mut rng = Rng::new(24);
+        let bound = BigUint::from(5);
+        let mut seen = [false; 5];
+        for _ in 0..100 {
+            seen[bound.random_below(&mut rng).to_u64().unwrap() as usize] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+
+        let big = &BigUint::from(u64::MAX) * 1000;
+        for _ in 0..100 {
+            assert!(big.random_below(&mut rng) < big);
+        }
+    }
+}
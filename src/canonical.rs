@@ -0,0 +1,169 @@
+use std::mem;
+
+use super::Regex;
+
+impl Regex {
+
+    /// An equivalent regex in a canonical form, so that regexes differing
+    /// only in how they group, order or repeat alternatives or intersected
+    /// operands, group concatenations, nest stars or complements or write
+    /// classes become equal. Alternatives and intersected operands are sorted
+    /// and deduplicated and, like concatenations, chained to the left as
+    /// `parse` does, `r**` becomes `r*`, `~~r` becomes `r` and classes are
+    /// replaced by `CharClass::canonical`.
+    ///
+    /// This preserves the language and capture groups, but not which of
+    /// several alternatives leftmost-first matching prefers.
+    pub fn canonical(&self) -> Regex {
+        match *self {
+            Regex::Nothing | Regex::Empty | Regex::Single(_) => self.clone(),
+            Regex::Class(ref k) => Regex::Class(k.canonical()),
+            Regex::Or(_, _) => {
+                let mut alternatives = vec![];
+                self.canonical_alternatives(&mut alternatives);
+                alternatives.sort();
+                alternatives.dedup();
+                Regex::alternation(alternatives)
+            },
+            Regex::Then(_, _) => {
+                let mut parts = vec![];
+                self.canonical_parts(&mut parts);
+                Regex::concatenation(parts)
+            },
+            Regex::Star(ref r) => match r.canonical() {
+                r @ Regex::Star(_) => r,
+                r => r.into_star(),
+            },
+            Regex::Capture(g, ref r) => Regex::Capture(g, Box::new(r.canonical())),
+            Regex::And(_, _) => {
+                let mut operands = vec![];
+                self.canonical_intersected(&mut operands);
+                operands.sort();
+                operands.dedup();
+                let mut operands = operands.into_iter();
+                let first = operands.next().unwrap();
+                operands.fold(first, Regex::into_and)
+            },
+            Regex::Not(ref r) => match r.canonical() {
+                Regex::Not(ref mut r) => mem::replace(r, Regex::Empty),
+                r => r.into_not(),
+            },
+        }
+    }
+
+    /// Pushes the canonical form of each operand of a chain of alternations.
+    /// None of them are alternations: other operators can canonicalize to
+    /// one, as `~~(?:a|b)` does, but its operands are then pushed instead.
+    fn canonical_alternatives(&self, alternatives: &mut Vec<Regex>) {
+        match *self {
+            Regex::Or(ref r, ref s) => {
+                r.canonical_alternatives(alternatives);
+                s.canonical_alternatives(alternatives);
+            },
+            _ => match self.canonical() {
+                r @ Regex::Or(_, _) => r.canonical_alternatives(alternatives),
+                r => alternatives.push(r),
+            },
+        }
+    }
+
+    /// Pushes the canonical form of each operand of a chain of
+    /// intersections, none of which are intersections
+    fn canonical_intersected(&self, operands: &mut Vec<Regex>) {
+        match *self {
+            Regex::And(ref r, ref s) => {
+                r.canonical_intersected(operands);
+                s.canonical_intersected(operands);
+            },
+            _ => match self.canonical() {
+                r @ Regex::And(_, _) => r.canonical_intersected(operands),
+                r => operands.push(r),
+            },
+        }
+    }
+
+    /// Pushes the canonical form of each operand of a chain of
+    /// concatenations from left to right, none of which are concatenations
+    fn canonical_parts(&self, parts: &mut Vec<Regex>) {
+        match *self {
+            Regex::Then(ref r, ref s) => {
+                r.canonical_parts(parts);
+                s.canonical_parts(parts);
+            },
+            _ => match self.canonical() {
+                r @ Regex::Then(_, _) => r.canonical_parts(parts),
+                r => parts.push(r),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::collections::HashSet;
+
+    use super::super::{NFA, Regex};
+    use super::super::test::strings;
+
+    fn canonical(pattern: &str) -> Regex {
+        Regex::parse(pattern).unwrap().canonical()
+    }
+
+    #[test]
+    fn test_canonical_equal() {
+        assert_eq!(canonical("a|b|c"), canonical("c|(?:b|a)"));
+        assert_eq!(canonical("a|b|a"), canonical("b|a"));
+        assert_eq!(canonical("a(?:bc)"), Regex::parse("abc").unwrap());
+        assert_eq!(canonical("(?:a**)**"), Regex::parse("a*").unwrap());
+        assert_eq!(canonical("[b-ca]|x"), canonical("x|[a-c]"));
+        assert_eq!(canonical("(b|a)"), canonical("(a|b)"));
+
+        assert_eq!(canonical("a~~(?:bc)"), canonical("abc"));
+        assert_eq!(canonical("c|~~(?:a|b)"), canonical("a|b|c"));
+        assert_eq!(canonical("a&~~(?:b&c)"), canonical("a&b&c"));
+        assert_eq!(canonical("b|~(?:~(?:a|c)|~(?:c|a))"), canonical("a|b|c"));
+
+        assert!(canonical("ab") != canonical("ba"));
+        assert!(canonical("(a)") != canonical("(?:a)"));
+    }
+
+    #[test]
+    fn test_canonical_idempotent() {
+        for pattern in &["c|(?:b|a)a*", "(?:(?:a|b)c)d**", "[^a-z]|[]|.", "((x|a))*", "a~~(?:bc)",
+                         "c|~~(?:a|b)", "a&~~(?:b&c)", "~~(?:~~(?:ab)c)|~~~(?:d&e)"] {
+            let r = canonical(pattern);
+            assert_eq!(r.canonical(), r, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_canonical_preserves_language() {
+        for pattern in &["c|(?:b|a)a*", "(?:(?:a|b)c)b**", "[^a]|[]|b", "(a|ab)(c|bcd)(d*)"] {
+            let r = Regex::parse(pattern).unwrap();
+            let n = NFA::from_regex(&r);
+            let m = NFA::from_regex(&r.canonical());
+            for s in strings(&['a', 'b', 'c', 'd'], 4) {
+                assert_eq!(n.accepts(&s), m.accepts(&s), "{} on {:?}", pattern, s);
+            }
+        }
+    }
+
+    #[test]
+    fn test_canonical_dedupes_hash_set() {
+        let forms: HashSet<Regex> = ["a|b", "b|a", "(?:a|b)|a", "[ab]", "[a-b]"].iter()
+            .map(|p| canonical(p))
+            .collect();
+        assert_eq!(forms.len(), 2);
+    }
+
+    #[test]
+    fn test_regex_ordering() {
+        let mut rs: Vec<Regex> = ["b*", "a", "", "ab", "[]", "a|b"].iter()
+            .map(|p| Regex::parse(p).unwrap())
+            .collect();
+        rs.sort();
+        let printed: Vec<String> = rs.iter().map(|r| r.to_string()).collect();
+        assert_eq!(printed, vec!["", "a", "[]", "a|b", "ab", "b*"]);
+    }
+}
@@ -0,0 +1,325 @@
+use std::mem;
+
+use super::{MatchKind, NFA, Regex};
+use super::sparse::SparseSet;
+
+/// The byte span of each group in a match, indexed by group number, with
+/// group 0 the whole match. Groups that took no part in the match are None,
+/// and a group inside a star reports its span in the last iteration.
+pub type Captures = Vec<Option<(usize, usize)>>;
+
+/// A thread of the Pike VM: an NFA state and the capture slots recorded on
+/// the way to it
+type Thread = (usize, Vec<Option<usize>>);
+
+impl Regex {
+
+    /// The spans of all groups in the leftmost match in `haystack`. With
+    /// `LeftmostFirst` the groups are those found by a backtracking matcher.
+    /// With `LeftmostLongest` the overall match is the longest, and, as in
+    /// POSIX, each group then matches as much as it can, in order of their
+    /// opening parentheses.
+    /// Groups inside intersections and complements never take part, but
+    /// are still counted.
+    pub fn captures(&self, haystack: &str, kind: MatchKind) -> Option<Captures> {
+        let nfa = NFA::from_regex(self);
+        if kind == MatchKind::LeftmostFirst {
+            // the NFA only has slots for the groups outside intersections
+            // and complements
+            return nfa.captures(haystack).map(|mut spans| {
+                spans.resize(self.num_groups() + 1, None);
+                spans
+            });
+        }
+
+        let (start, end) = nfa.find(haystack)?;
+        let xs: Vec<char> = haystack[start..end].chars().collect();
+        // the byte offset of each character boundary in the match
+        let mut offsets = vec![start];
+        offsets.extend(haystack[start..end].char_indices().map(|(i, c)| start + i + c.len_utf8()));
+
+        let mut spans = vec![None; self.num_groups() + 1];
+        spans[0] = Some((0, xs.len()));
+        self.posix_groups(&xs, 0, xs.len(), &mut spans);
+        Some(spans.into_iter().map(|s| s.map(|(i, j)| (offsets[i], offsets[j]))).collect())
+    }
+
+    /// The highest group number used
+    fn num_groups(&self) -> usize {
+        match *self {
+            Regex::Nothing | Regex::Empty | Regex::Single(_) | Regex::Class(_) => 0,
+            Regex::Or(ref r, ref s) | Regex::Then(ref r, ref s) => r.num_groups().max(s.num_groups()),
+            Regex::And(ref r, ref s) => r.num_groups().max(s.num_groups()),
+            Regex::Star(ref r) | Regex::Not(ref r) => r.num_groups(),
+            Regex::Capture(g, ref r) => g.max(r.num_groups()),
+        }
+    }
+
+    /// The operands of a chain of concatenations, from left to right
+    fn concatenated_parts<'a>(&'a self, parts: &mut Vec<&'a Regex>) {
+        match *self {
+            Regex::Then(ref r, ref s) => {
+                r.concatenated_parts(parts);
+                s.concatenated_parts(parts);
+            },
+            _ => parts.push(self),
+        }
+    }
+
+    /// Records in `spans` the character spans of the groups when this regex
+    /// matches `xs[i..j]`, which it must. Each part of a concatenation, from
+    /// left to right, and each iteration of a star take the longest match
+    /// that still lets the whole succeed, and alternation prefers its left
+    /// operand.
+    ///
+    /// Rather than trying every split point, where the rest of a
+    /// concatenation or star can end the match is found in one backward
+    /// pass, and where the part before it can end in one forward pass.
+    fn posix_groups(&self, xs: &[char], i: usize, j: usize, spans: &mut Vec<Option<(usize, usize)>>) {
+        match *self {
+            Regex::Nothing | Regex::Empty | Regex::Single(_) | Regex::Class(_) => {},
+            Regex::Or(ref r, ref s) => {
+                if NFA::from_regex(r).accepts(&xs[i..j]) {
+                    r.posix_groups(xs, i, j, spans);
+                } else {
+                    s.posix_groups(xs, i, j, spans);
+                }
+            },
+            Regex::Then(_, _) => {
+                // split a chain like `(a|ab)(c|bcd)(d*)` one part at a time
+                // rather than as nested pairs, so that `(a|ab)` gets first say
+                let mut parts = vec![];
+                self.concatenated_parts(&mut parts);
+                let mut i = i;
+                for (p, part) in parts.iter().enumerate() {
+                    let rest = Regex::concatenation(parts[p + 1..].iter().map(|&r| r.clone()));
+                    let completes = matched_suffixes(&rest, &xs[i..j]);
+                    let ends = NFA::from_regex(part).accepted_prefixes(&xs[i..j]);
+                    let k = i + (0..ends.len()).rev().find(|&k| ends[k] && completes[k]).unwrap();
+                    part.posix_groups(xs, i, k, spans);
+                    i = k;
+                }
+            },
+            Regex::Star(ref r) => {
+                // one iteration at a time rather than recursing on the rest,
+                // so long matches cannot overflow the stack
+                let completes = matched_suffixes(self, &xs[i..j]);
+                let body = NFA::from_regex(r);
+                let start = i;
+                let mut i = i;
+                while i < j {
+                    // iterations are non-empty, as an empty one adds nothing
+                    let ends = body.accepted_prefixes(&xs[i..j]);
+                    let k = i + (1..ends.len()).rev().find(|&k| ends[k] && completes[i + k - start]).unwrap();
+                    r.posix_groups(xs, i, k, spans);
+                    i = k;
+                }
+            },
+            Regex::Capture(g, ref r) => {
+                spans[g] = Some((i, j));
+                r.posix_groups(xs, i, j, spans);
+            },
+            // as with leftmost-first matching, groups in these are not reported
+            Regex::And(_, _) | Regex::Not(_) => {},
+        }
+    }
+}
+
+/// Whether `r` matches `xs[k..]`, for each k from 0 to `xs.len()`, found by
+/// running its reversed NFA backwards over `xs`
+fn matched_suffixes(r: &Regex, xs: &[char]) -> Vec<bool> {
+    let reversed: Vec<char> = xs.iter().rev().cloned().collect();
+    let mut matched = NFA::from_regex(r).reverse().accepted_prefixes(&reversed);
+    matched.resize(xs.len() + 1, false);
+    matched.reverse();
+    matched
+}
+
+impl NFA {
+
+    /// The spans of all groups in the leftmost-first match in `haystack`,
+    /// found by a Pike VM: a simulation whose threads are kept in priority
+    /// order, each carrying the positions at which it entered save nodes.
+    /// As in `find_at`, this is a single pass, in which a thread for a match
+    /// beginning at each position is added behind the others until a match
+    /// is found. The first slot of each thread holds where its match began.
+    pub fn captures(&self, haystack: &str) -> Option<Captures> {
+        let num_slots = self.nodes.iter().filter_map(|n| n.save).max().map_or(2, |s| (s | 1) + 1);
+        let mut seen = SparseSet::new(self.nodes.len());
+        let mut threads = vec![];
+        let mut next = vec![];
+        let mut matched: Option<(usize, Vec<Option<usize>>)> = None;
+        let mut pos = 0;
+        let mut chars = haystack.chars();
+        loop {
+            // seen holds the states of threads, so a new match starting here
+            // only adds the states no earlier thread has reached
+            if matched.is_none() {
+                let mut slots = vec![None; num_slots];
+                slots[0] = Some(pos);
+                self.add_capture_thread(&mut threads, &mut seen, self.start_idx, slots, pos);
+            }
+            if threads.is_empty() {
+                break;
+            }
+            let c = chars.next();
+            seen.clear();
+            for &(t, ref slots) in threads.iter() {
+                if let Some(c) = c {
+                    for &(label, u) in self.nodes[t].transitions.iter() {
+                        match label {
+                            Some((lo, hi)) if lo <= c && c <= hi => {
+                                let at = pos + c.len_utf8();
+                                self.add_capture_thread(&mut next, &mut seen, u, slots.clone(), at);
+                            },
+                            _ => {}
+                        }
+                    }
+                }
+                // lower priority threads can only give less preferred matches
+                if self.is_accepting(t) {
+                    matched = Some((pos, slots.clone()));
+                    break;
+                }
+            }
+            match c {
+                Some(c) => pos += c.len_utf8(),
+                None => break,
+            }
+            mem::swap(&mut threads, &mut next);
+            next.clear();
+        }
+        let (end, slots) = matched?;
+        let mut spans: Captures = slots.chunks(2).map(|s| match (s[0], s[1]) {
+            (Some(i), Some(j)) => Some((i, j)),
+            _ => None,
+        }).collect();
+        spans[0] = Some((slots[0].unwrap(), end));
+        Some(spans)
+    }
+
+    /// Whether this accepts `xs[..k]`, for each k from 0 to `xs.len()`,
+    /// stopping early once no state is left, so that a short prefix costs
+    /// no more than its length. Any k past the end is not accepted.
+    fn accepted_prefixes(&self, xs: &[char]) -> Vec<bool> {
+        let mut accepted = vec![];
+        let mut states = SparseSet::new(self.nodes.len());
+        let mut next = SparseSet::new(self.nodes.len());
+        let mut stack = vec![];
+        self.add_closure(&mut states, &mut stack, self.start_idx);
+        for &c in xs.iter() {
+            accepted.push(states.iter().any(|&s| self.is_accepting(s)));
+            next.clear();
+            for &s in states.iter() {
+                for &(label, t) in self.nodes[s].transitions.iter() {
+                    match label {
+                        Some((lo, hi)) if lo <= c && c <= hi => self.add_closure(&mut next, &mut stack, t),
+                        _ => {}
+                    }
+                }
+            }
+            mem::swap(&mut states, &mut next);
+            if states.is_empty() {
+                return accepted;
+            }
+        }
+        accepted.push(states.iter().any(|&s| self.is_accepting(s)));
+        accepted
+    }
+
+    /// Adds threads for `s` and the states reachable from it by e-steps in
+    /// priority order, recording `pos` in the slots of any save nodes passed
+    fn add_capture_thread(&self,
+                          threads: &mut Vec<Thread>,
+                          seen: &mut SparseSet,
+                          s: usize,
+                          slots: Vec<Option<usize>>,
+                          pos: usize) {
+        let mut stack = vec![(s, slots)];
+        while let Some((s, mut slots)) = stack.pop() {
+            if !seen.insert(s) {
+                continue;
+            }
+            if let Some(slot) = self.nodes[s].save {
+                slots[slot] = Some(pos);
+            }
+            // reversed so that the first transition is explored first
+            for &(label, t) in self.nodes[s].transitions.iter().rev() {
+                if label.is_none() {
+                    stack.push((t, slots.clone()));
+                }
+            }
+            threads.push((s, slots));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::super::{MatchKind, NFA, Regex};
+
+    fn captures(pattern: &str, haystack: &str, kind: MatchKind) -> Option<Vec<Option<(usize, usize)>>> {
+        Regex::parse(pattern).unwrap().captures(haystack, kind)
+    }
+
+    fn both(pattern: &str, haystack: &str) -> Option<Vec<Option<(usize, usize)>>> {
+        let first = captures(pattern, haystack, MatchKind::LeftmostFirst);
+        let longest = captures(pattern, haystack, MatchKind::LeftmostLongest);
+        assert_eq!(first, longest, "{} on {:?}", pattern, haystack);
+        first
+    }
+
+    #[test]
+    fn test_captures() {
+        assert_eq!(both("\\\\x([0-9a-f]+)", "say \"\\x41\""), Some(vec![Some((5, 9)), Some((7, 9))]));
+        assert_eq!(both("(a)|(b)", "xb"), Some(vec![Some((1, 2)), None, Some((1, 2))]));
+        assert_eq!(both("(a*)(a*)", "aaa"), Some(vec![Some((0, 3)), Some((0, 3)), Some((3, 3))]));
+        assert_eq!(both("(a|b)*", "abb"), Some(vec![Some((0, 3)), Some((2, 3))]));
+        assert_eq!(both("((a)(b)?)+", "aba"), Some(vec![Some((0, 3)), Some((2, 3)), Some((2, 3)), Some((1, 2))]));
+        assert_eq!(both("(é)(x)", "éx"), Some(vec![Some((0, 3)), Some((0, 2)), Some((2, 3))]));
+        assert_eq!(both("(x)", "abc"), None);
+        assert_eq!(both("a", "a"), Some(vec![Some((0, 1))]));
+    }
+
+    #[test]
+    fn test_captures_semantics() {
+        let first = |p: &str, h: &str| captures(p, h, MatchKind::LeftmostFirst);
+        let posix = |p: &str, h: &str| captures(p, h, MatchKind::LeftmostLongest);
+
+        assert_eq!(first("(a|ab)(c|bcd)(d*)", "abcd"),
+                   Some(vec![Some((0, 4)), Some((0, 1)), Some((1, 4)), Some((4, 4))]));
+        assert_eq!(posix("(a|ab)(c|bcd)(d*)", "abcd"),
+                   Some(vec![Some((0, 4)), Some((0, 2)), Some((2, 3)), Some((3, 4))]));
+        assert_eq!(first("(a|ab)(b*)", "abb"), Some(vec![Some((0, 3)), Some((0, 1)), Some((1, 3))]));
+        assert_eq!(posix("(a|ab)(b*)", "abb"), Some(vec![Some((0, 3)), Some((0, 2)), Some((2, 3))]));
+        assert_eq!(first("(a)|ab", "ab"), Some(vec![Some((0, 1)), Some((0, 1))]));
+        assert_eq!(posix("(a)|ab", "ab"), Some(vec![Some((0, 2)), None]));
+    }
+
+    #[test]
+    fn test_captures_hidden_groups() {
+        // groups inside intersections and complements never take part
+        assert_eq!(both("(a)&a", "a"), Some(vec![Some((0, 1)), None]));
+        assert_eq!(both("(b)(~(a)|c)", "bc"), Some(vec![Some((0, 2)), Some((0, 1)), Some((1, 2)), None]));
+    }
+
+    #[test]
+    fn test_captures_long_input() {
+        // matching again at every split point, and restarting at every
+        // position, took quadratic time here, and recursing once per
+        // iteration overflowed the stack
+        let haystack = "a".repeat(20000);
+        assert_eq!(both("(a)*", &haystack), Some(vec![Some((0, 20000)), Some((19999, 20000))]));
+        assert_eq!(both("(a*)0", &haystack), None);
+    }
+
+    #[test]
+    fn test_nfa_captures_agrees_with_find() {
+        let n = NFA::from_regex(&Regex::parse("([a-z]+)([0-9]*)").unwrap());
+        for h in &["  ab12 ", "9x", "", "12"] {
+            let spans = n.captures(h);
+            assert_eq!(spans.map(|s| s[0].unwrap()), n.find_at(h, 0, MatchKind::LeftmostFirst));
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::char;
+
+/// A set of characters, given as a list of inclusive ranges which may
+/// optionally be negated
+#[derive(Debug,Clone,PartialEq,Eq,Hash,PartialOrd,Ord)]
+pub struct CharClass {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl CharClass {
+
+    /// Panics if any range has its lower bound above its upper bound
+    pub fn new(ranges: &[(char, char)], negated: bool) -> CharClass {
+        for &(lo, hi) in ranges {
+            assert!(lo <= hi, "invalid class range {:?}-{:?}", lo, hi);
+        }
+        CharClass { ranges: ranges.to_vec(), negated: negated }
+    }
+
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+
+    /// The ranges as written, before any negation is applied
+    pub fn raw_ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != self.negated
+    }
+
+    /// The characters in this class as sorted, disjoint and non-adjacent
+    /// inclusive ranges, with any negation already applied
+    pub fn ranges(&self) -> Vec<(char, char)> {
+        let merged = merge(&self.ranges);
+        if self.negated { complement(&merged) } else { merged }
+    }
+
+    /// The same characters as sorted, disjoint and non-adjacent ranges,
+    /// negated only if that takes fewer ranges, so that any two classes
+    /// with the same members have the same canonical form
+    pub fn canonical(&self) -> CharClass {
+        let ranges = self.ranges();
+        let complemented = complement(&ranges);
+        if complemented.len() < ranges.len() {
+            CharClass { ranges: complemented, negated: true }
+        } else {
+            CharClass { ranges: ranges, negated: false }
+        }
+    }
+}
+
+/// Sorts ranges and merges any that overlap or touch
+fn merge(ranges: &[(char, char)]) -> Vec<(char, char)> {
+    let mut sorted = ranges.to_vec();
+    sorted.sort();
+    let mut merged: Vec<(char, char)> = vec![];
+    for (lo, hi) in sorted {
+        if let Some(last) = merged.last_mut() {
+            if last.1 >= lo || succ(last.1) == Some(lo) {
+                if hi > last.1 {
+                    last.1 = hi;
+                }
+                continue;
+            }
+        }
+        merged.push((lo, hi));
+    }
+    merged
+}
+
+/// Complements sorted, merged ranges with respect to all of `char`
+fn complement(ranges: &[(char, char)]) -> Vec<(char, char)> {
+    let mut result = vec![];
+    let mut next = Some('\0');
+    for &(lo, hi) in ranges {
+        if let Some(n) = next {
+            if n < lo {
+                result.push((n, pred(lo).unwrap()));
+            }
+        }
+        next = succ(hi);
+    }
+    if let Some(n) = next {
+        result.push((n, char::MAX));
+    }
+    result
+}
+
+/// The next `char` after `c`, skipping the surrogate range
+pub fn succ(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        char::MAX => None,
+        c => char::from_u32(c as u32 + 1),
+    }
+}
+
+/// The `char` before `c`, skipping the surrogate range
+pub fn pred(c: char) -> Option<char> {
+    match c {
+        '\u{E000}' => Some('\u{D7FF}'),
+        '\0' => None,
+        c => char::from_u32(c as u32 - 1),
+    }
+}
+
+/// The number of characters from `lo` to `hi` inclusive, leaving out the
+/// surrogate code points that are not characters
+pub fn range_size(lo: char, hi: char) -> u32 {
+    let (lo, hi) = (lo as u32, hi as u32);
+    let surrogates = if lo < 0xD800 && hi > 0xDFFF { 0x800 } else { 0 };
+    hi - lo + 1 - surrogates
+}
+
+/// The character `k` places after `lo`, skipping the surrogate range
+pub fn nth_from(lo: char, k: u32) -> char {
+    let lo = lo as u32;
+    let mut c = lo + k;
+    if lo < 0xD800 && c >= 0xD800 {
+        c += 0x800;
+    }
+    char::from_u32(c).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::char;
+    use super::CharClass;
+
+    #[test]
+    fn test_class_ranges() {
+        let c = CharClass::new(&[('x', 'z'), ('a', 'c'), ('b', 'f'), ('g', 'g')], false);
+
+        assert_eq!(c.ranges(), vec![('a', 'g'), ('x', 'z')]);
+        assert!(c.contains('d'));
+        assert!(!c.contains('h'));
+    }
+
+    #[test]
+    fn test_class_negated() {
+        let c = CharClass::new(&[('b', 'y')], true);
+
+        assert_eq!(c.ranges(), vec![('\0', 'a'), ('z', char::MAX)]);
+        assert!(c.contains('a'));
+        assert!(!c.contains('m'));
+
+        let all = CharClass::new(&[], true);
+        assert_eq!(all.ranges(), vec![('\0', char::MAX)]);
+
+        let surrogates = CharClass::new(&[('\0', '\u{D7FF}')], true);
+        assert_eq!(surrogates.ranges(), vec![('\u{E000}', char::MAX)]);
+    }
+
+    #[test]
+    fn test_class_canonical() {
+        let c = CharClass::new(&[('x', 'z'), ('a', 'c'), ('b', 'f')], false);
+        assert_eq!(c.canonical(), CharClass::new(&[('a', 'f'), ('x', 'z')], false));
+
+        let c = CharClass::new(&[('\0', 'a'), ('c', char::MAX)], false);
+        assert_eq!(c.canonical(), CharClass::new(&[('b', 'b')], true));
+        assert_eq!(CharClass::new(&[('b', 'b')], true).canonical(), c.canonical());
+
+        assert_eq!(CharClass::new(&[], true).canonical(), CharClass::new(&[], true));
+        assert_eq!(CharClass::new(&[], false).canonical(), CharClass::new(&[], false));
+    }
+}
@@ -0,0 +1,165 @@
+use super::{BigUint, DFA, NFA, Rng};
+
+/// Counting and sampling the accepted strings of a given length, from the
+/// number of paths of each length from each state to an accepting one. A
+/// DFA has exactly one path for each string, so paths are never counted
+/// twice.
+impl DFA {
+
+    /// The number of distinct accepted strings of exactly `len` characters
+    pub fn count_accepted(&self, len: usize) -> BigUint {
+        let mut counts = self.final_counts();
+        for _ in 0..len {
+            counts = self.next_counts(&counts);
+        }
+        counts[self.start()].clone()
+    }
+
+    /// An accepted string of exactly `len` characters, drawn uniformly at
+    /// random from all of them, or None if there are none
+    pub fn sample_accepted(&self, len: usize, rng: &mut Rng) -> Option<Vec<char>> {
+        // counts[k][s]: the number of strings of k characters accepted from s
+        let mut counts = vec![self.final_counts()];
+        for k in 0..len {
+            let next = self.next_counts(&counts[k]);
+            counts.push(next);
+        }
+        if counts[len][self.start()].is_zero() {
+            return None;
+        }
+
+        let mut xs = Vec::with_capacity(len);
+        let mut s = self.start();
+        for k in (0..len).rev() {
+            // each class gets a share of the strings from s in proportion to
+            // the number that continue through it
+            let mut x = counts[k + 1][s].random_below(rng);
+            for c in 0..self.alphabet().len() {
+                let t = match self.next_class(s, c) {
+                    Some(t) => t,
+                    None => continue,
+                };
+                let share = &counts[k][t] * self.alphabet().size(c);
+                if x < share {
+                    let size = BigUint::from(self.alphabet().size(c) as u64);
+                    let i = size.random_below(rng).to_u64().unwrap() as u32;
+                    xs.push(self.alphabet().nth(c, i));
+                    s = t;
+                    break;
+                }
+                x = &x - &share;
+            }
+        }
+        Some(xs)
+    }
+
+    /// One for each accepting state and zero for the others
+    fn final_counts(&self) -> Vec<BigUint> {
+        (0..self.num_states()).map(|s| BigUint::from(self.is_accepting(s) as u64)).collect()
+    }
+
+    /// The number of strings one character longer accepted from each state,
+    /// given `counts` for each state
+    fn next_counts(&self, counts: &[BigUint]) -> Vec<BigUint> {
+        (0..self.num_states()).map(|s| {
+            let mut total = BigUint::zero();
+            for c in 0..self.alphabet().len() {
+                if let Some(t) = self.next_class(s, c) {
+                    if !counts[t].is_zero() {
+                        total = &total + &(&counts[t] * self.alphabet().size(c));
+                    }
+                }
+            }
+            total
+        }).collect()
+    }
+}
+
+/// The same through the NFA's DFA. To draw many samples, build the DFA once
+/// and sample from that instead.
+impl NFA {
+
+    /// The number of distinct accepted strings of exactly `len` characters
+    pub fn count_accepted(&self, len: usize) -> BigUint {
+        DFA::from_nfa(self).count_accepted(len)
+    }
+
+    /// A uniformly random accepted string of exactly `len` characters
+    pub fn sample_accepted(&self, len: usize, rng: &mut Rng) -> Option<Vec<char>> {
+        DFA::from_nfa(self).sample_accepted(len, rng)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::collections::HashMap;
+
+    use super::super::{DFA, NFA, Regex, Rng};
+    use super::super::test::strings;
+
+    fn nfa(pattern: &str) -> NFA {
+        NFA::from_regex(&Regex::parse(pattern).unwrap())
+    }
+
+    fn count(pattern: &str, len: usize) -> String {
+        nfa(pattern).count_accepted(len).to_string()
+    }
+
+    #[test]
+    fn test_count_accepted() {
+        assert_eq!(count("[a-c]{2}", 2), "9");
+        assert_eq!(count("[a-c]{2}", 3), "0");
+        assert_eq!(count("a|a|[a-a]", 1), "1");
+        assert_eq!(count("a*", 50), "1");
+        assert_eq!(count("(?:a|b)*", 100), "1267650600228229401496703205376");
+        assert_eq!(count(".", 1), "1112064");
+        assert_eq!(count("[^\u{d7ff}-\u{e000}]", 1), "1112062");
+        assert_eq!(count(".{3}", 3), "1375274358112518144");
+        assert_eq!(count("", 0), "1");
+        assert_eq!(count("[]", 0), "0");
+    }
+
+    #[test]
+    fn test_count_brute_force() {
+        let all = strings(&['a', 'b', 'c'], 5);
+        for pattern in &["(?:a|b)*c", "a(?:b|c)*|c*", "(?:ab|ba|c)+", "a?b?c?", "[ab]*&~(?:.*aa.*)"] {
+            let n = nfa(pattern);
+            let d = DFA::from_nfa(&n);
+            for len in 0..6 {
+                let expected = all.iter().filter(|xs| xs.len() == len && n.accepts(xs)).count();
+                assert_eq!(d.count_accepted(len).to_u64(), Some(expected as u64), "{} at {}", pattern, len);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sample_accepted() {
+        let mut rng = Rng::new(24);
+        let n = nfa("a(?:b|cd)");
+        assert_eq!(n.sample_accepted(2, &mut rng), Some(vec!['a', 'b']));
+        assert_eq!(n.sample_accepted(3, &mut rng), Some(vec!['a', 'c', 'd']));
+        assert_eq!(n.sample_accepted(4, &mut rng), None);
+
+        let d = DFA::from_nfa(&nfa("[^a]{2}|a.*"));
+        for _ in 0..100 {
+            let xs = d.sample_accepted(5, &mut rng).unwrap();
+            assert_eq!(xs.len(), 5);
+            assert!(d.accepts(&xs), "{:?}", xs);
+        }
+    }
+
+    #[test]
+    fn test_sample_uniform() {
+        // 26 strings start with a and one with b, so an even choice between
+        // the branches would give bc half the time
+        let d = DFA::from_nfa(&nfa("a[a-z]|bc"));
+        let mut rng = Rng::new(7);
+        let mut seen: HashMap<Vec<char>, usize> = HashMap::new();
+        for _ in 0..2700 {
+            *seen.entry(d.sample_accepted(2, &mut rng).unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(seen.len(), 27);
+        assert!(seen.values().all(|&k| 50 < k && k < 150), "{:?}", seen);
+    }
+}
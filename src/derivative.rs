@@ -0,0 +1,162 @@
+use super::Regex;
+
+/// Matching by Brzozowski derivatives, working directly on the syntax tree
+/// rather than on an `NFA`. The derivative of `r` by `c` matches exactly the
+/// strings `s` for which `r` matches `cs`.
+impl Regex {
+
+    /// Whether the empty string is in the language of this regex
+    pub fn nullable(&self) -> bool {
+        match *self {
+            Regex::Nothing => false,
+            Regex::Empty => true,
+            Regex::Single(_) => false,
+            Regex::Class(_) => false,
+            Regex::Or(ref r, ref s) => r.nullable() || s.nullable(),
+            Regex::Then(ref r, ref s) => r.nullable() && s.nullable(),
+            Regex::Star(_) => true,
+            Regex::Capture(_, ref r) => r.nullable(),
+            Regex::And(ref r, ref s) => r.nullable() && s.nullable(),
+            Regex::Not(ref r) => !r.nullable(),
+        }
+    }
+
+    /// The derivative of this regex with respect to `c`, simplified as it is
+    /// built so that repeated derivatives stay small
+    pub fn derivative(&self, c: char) -> Regex {
+        match *self {
+            Regex::Nothing | Regex::Empty => Regex::Nothing,
+            Regex::Single(a) => if a == c { Regex::Empty } else { Regex::Nothing },
+            Regex::Class(ref k) => if k.contains(c) { Regex::Empty } else { Regex::Nothing },
+            Regex::Or(ref r, ref s) => or(r.derivative(c), s.derivative(c)),
+            Regex::Then(ref r, ref s) => {
+                let left = then(r.derivative(c), (**s).clone());
+                if r.nullable() {
+                    or(left, s.derivative(c))
+                } else {
+                    left
+                }
+            },
+            Regex::Star(ref r) => then(r.derivative(c), star((**r).clone())),
+            // derivatives only decide membership, so groups can be dropped
+            Regex::Capture(_, ref r) => r.derivative(c),
+            Regex::And(ref r, ref s) => and(r.derivative(c), s.derivative(c)),
+            Regex::Not(ref r) => r.derivative(c).into_not(),
+        }
+    }
+
+    /// Whether this regex matches the whole of `xs`, computed by taking
+    /// successive derivatives
+    pub fn matches(&self, xs: &[char]) -> bool {
+        let mut r = self.clone();
+        for &c in xs.iter() {
+            r = r.derivative(c);
+            if r == Regex::Nothing {
+                return false;
+            }
+        }
+        r.nullable()
+    }
+}
+
+/// Alternation, removing `Nothing` and duplicate operands
+fn or(r: Regex, s: Regex) -> Regex {
+    if r == Regex::Nothing || r == s {
+        s
+    } else if s == Regex::Nothing {
+        r
+    } else {
+        Regex::Or(Box::new(r), Box::new(s))
+    }
+}
+
+/// Intersection, absorbing `Nothing` and removing duplicate operands
+fn and(r: Regex, s: Regex) -> Regex {
+    if r == Regex::Nothing || r == s {
+        r
+    } else if s == Regex::Nothing {
+        s
+    } else {
+        r.into_and(s)
+    }
+}
+
+/// Repetition, with `(r*)* = r*` and `∅* = ε* = ε`
+fn star(r: Regex) -> Regex {
+    match r {
+        Regex::Nothing | Regex::Empty => Regex::Empty,
+        r @ Regex::Star(_) => r,
+        r => r.into_star(),
+    }
+}
+
+/// Concatenation, absorbing `Nothing` and dropping `Empty`
+fn then(r: Regex, s: Regex) -> Regex {
+    match (r, s) {
+        (Regex::Nothing, _) | (_, Regex::Nothing) => Regex::Nothing,
+        (Regex::Empty, s) => s,
+        (r, Regex::Empty) => r,
+        (r, s) => Regex::Then(Box::new(r), Box::new(s)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::super::{NFA, Regex};
+    use super::super::test::strings;
+
+    fn assert_agrees(pattern: &str) {
+        let r = Regex::parse(pattern).unwrap();
+        let nfa = NFA::from_regex(&r);
+        for s in strings(&['a', 'b', 'c'], 5) {
+            assert_eq!(nfa.accepts(&s), r.matches(&s), "{} on {:?}", pattern, s);
+        }
+    }
+
+    #[test]
+    fn test_nullable() {
+        assert!(Regex::Empty.nullable());
+        assert!(!Regex::Nothing.nullable());
+        assert!(Regex::parse("a*").unwrap().nullable());
+        assert!(Regex::parse("a*b?").unwrap().nullable());
+        assert!(!Regex::parse("a*b").unwrap().nullable());
+        assert!(Regex::parse("a|b*").unwrap().nullable());
+    }
+
+    #[test]
+    fn test_derivative() {
+        let r = Regex::parse("ab|ac").unwrap();
+
+        assert_eq!(r.derivative('a'), Regex::parse("b|c").unwrap());
+        assert_eq!(r.derivative('b'), Regex::Nothing);
+        assert_eq!(r.derivative('a').derivative('c'), Regex::Empty);
+
+        let s = Regex::parse("a*").unwrap();
+        assert_eq!(s.derivative('a'), s);
+
+        // the star left after the derivative of its operand is normalized
+        let t = Regex::parse("(?:a*)*").unwrap();
+        assert_eq!(t.derivative('a'), Regex::parse("a*a*").unwrap());
+        assert_eq!(Regex::parse("(?:(?:ab)*)*").unwrap().derivative('a'), Regex::parse("b(?:ab)*(?:ab)*").unwrap());
+    }
+
+    #[test]
+    fn test_derivative_agrees_with_nfa() {
+        assert_agrees("a");
+        assert_agrees("a|b|c");
+        assert_agrees("ab");
+        assert_agrees("(a|b)*");
+        assert_agrees("(a|b)*abb");
+        assert_agrees("[^b]+b?");
+        assert_agrees("(ab|a)(bc|c)*");
+        assert_agrees("a{2,3}|(bc)*");
+        assert_agrees("");
+        assert_agrees("[]*a");
+        assert_agrees("(?:a*)*b|(?:(?:ab)*)*");
+        assert_agrees("(a|b)*&~(?:.*bb.*)");
+        assert_agrees("~(?:a*)|c");
+        assert_agrees("~(?:)");
+        assert_agrees("(?:a|b)*b&(?:a|c)*a|~(?:b*)c");
+    }
+}
@@ -0,0 +1,597 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use super::NFA;
+use super::alphabet::Alphabet;
+
+/// A deterministic automaton over the classes of an `Alphabet`, with a dense
+/// transition table. Missing transitions lead to an implicit dead state.
+#[derive(Debug,Clone)]
+pub struct DFA {
+    alphabet: Alphabet,
+    /// The transition from state `s` on class `c` is at `s * alphabet.len() + c`
+    transitions: Vec<Option<usize>>,
+    accepting: Vec<bool>,
+    /// The earliest rule tagging any NFA state in each DFA state
+    rules: Vec<Option<usize>>,
+    start_idx: usize,
+}
+
+impl DFA {
+
+    pub fn from_nfa(nfa: &NFA) -> DFA {
+        Self::from_nfa_with_alphabet(nfa, Alphabet::from_nfa(nfa))
+    }
+
+    /// Subset construction. The alphabet must be at least as fine as the
+    /// one built by `Alphabet::from_nfa`.
+    pub fn from_nfa_with_alphabet(nfa: &NFA, alphabet: Alphabet) -> DFA {
+        let mut start = HashSet::new();
+        start.insert(nfa.start_idx);
+        nfa.epsilon_closure(&mut start);
+
+        let mut dfa = DFA {
+            alphabet: alphabet,
+            transitions: vec![],
+            accepting: vec![],
+            rules: vec![],
+            start_idx: 0,
+        };
+        // Each DFA state is identified by its sorted set of NFA states
+        let mut ids: HashMap<Vec<usize>, usize> = HashMap::new();
+        let mut sets = vec![];
+        dfa.add_state(nfa, &mut ids, &mut sets, start);
+
+        let mut s = 0;
+        while s < sets.len() {
+            for c in 0..dfa.alphabet.len() {
+                let (lo, _) = dfa.alphabet.range(c);
+                let mut next = nfa.step(&sets[s], Some(lo));
+                if next.is_empty() {
+                    continue;
+                }
+                nfa.epsilon_closure(&mut next);
+                let t = dfa.add_state(nfa, &mut ids, &mut sets, next);
+                let width = dfa.alphabet.len();
+                dfa.transitions[s * width + c] = Some(t);
+            }
+            s += 1;
+        }
+        dfa
+    }
+
+    /// Returns the id of the state for `set`, creating it if necessary
+    fn add_state(&mut self,
+                 nfa: &NFA,
+                 ids: &mut HashMap<Vec<usize>, usize>,
+                 sets: &mut Vec<HashSet<usize>>,
+                 set: HashSet<usize>) -> usize {
+        let mut key = set.iter().cloned().collect::<Vec<usize>>();
+        key.sort();
+        if let Some(&id) = ids.get(&key) {
+            return id;
+        }
+        let id = sets.len();
+        ids.insert(key, id);
+        self.accepting.push(nfa.is_accepting_set(&set));
+        self.rules.push(nfa.rule_of_set(&set));
+        self.transitions.extend(vec![None; self.alphabet.len()]);
+        sets.push(set);
+        id
+    }
+
+    pub fn alphabet(&self) -> &Alphabet {
+        &self.alphabet
+    }
+
+    pub fn num_states(&self) -> usize {
+        self.accepting.len()
+    }
+
+    pub fn start(&self) -> usize {
+        self.start_idx
+    }
+
+    pub fn is_accepting(&self, state: usize) -> bool {
+        self.accepting[state]
+    }
+
+    /// The earliest rule accepted in `state`, if it is tagged with any
+    pub fn rule(&self, state: usize) -> Option<usize> {
+        self.rules[state]
+    }
+
+    /// The state reached from `state` on a character of class `class`
+    pub fn next_class(&self, state: usize, class: usize) -> Option<usize> {
+        self.transitions[state * self.alphabet.len() + class]
+    }
+
+    /// The state reached from `state` on `c`
+    pub fn next(&self, state: usize, c: char) -> Option<usize> {
+        self.next_class(state, self.alphabet.class_of(c))
+    }
+
+    pub fn accepts(&self, xs: &[char]) -> bool {
+        let mut state = self.start_idx;
+        for &c in xs.iter() {
+            state = match self.next(state, c) {
+                Some(s) => s,
+                None => return false,
+            };
+        }
+        self.accepting[state]
+    }
+
+    /// The product automaton, which runs `a` and `b` in lockstep and accepts
+    /// where `accept` holds of whether each of them accepts. Both must be
+    /// over the same alphabet. Rule tags are not preserved.
+    pub fn product<F>(a: &DFA, b: &DFA, accept: F) -> DFA
+        where F: Fn(bool, bool) -> bool
+    {
+        assert!(a.alphabet == b.alphabet, "product of DFAs over different alphabets");
+        let width = a.alphabet.len();
+        let mut dfa = DFA {
+            alphabet: a.alphabet.clone(),
+            transitions: vec![],
+            accepting: vec![],
+            rules: vec![],
+            start_idx: 0,
+        };
+        // A component of None means that automaton is in its dead state
+        let mut ids: HashMap<(Option<usize>, Option<usize>), usize> = HashMap::new();
+        let mut pairs = vec![(Some(a.start_idx), Some(b.start_idx))];
+        ids.insert(pairs[0], 0);
+
+        let mut s = 0;
+        while s < pairs.len() {
+            let (p, q) = pairs[s];
+            dfa.accepting.push(accept(p.is_some_and(|p| a.accepting[p]),
+                                      q.is_some_and(|q| b.accepting[q])));
+            dfa.rules.push(None);
+            for c in 0..width {
+                let next = (p.and_then(|p| a.next_class(p, c)), q.and_then(|q| b.next_class(q, c)));
+                let t = if next == (None, None) {
+                    None
+                } else {
+                    let id = pairs.len();
+                    let t = *ids.entry(next).or_insert(id);
+                    if t == id {
+                        pairs.push(next);
+                    }
+                    Some(t)
+                };
+                dfa.transitions.push(t);
+            }
+            s += 1;
+        }
+        // The pair of dead states is not itself a state, so if it would have
+        // been accepting the other states still need to be able to reach it
+        if accept(false, false) {
+            let dead = dfa.num_states();
+            dfa.accepting.push(true);
+            dfa.rules.push(None);
+            dfa.transitions.extend(vec![Some(dead); width]);
+            for t in dfa.transitions.iter_mut() {
+                if t.is_none() {
+                    *t = Some(dead);
+                }
+            }
+        }
+        dfa
+    }
+
+    /// The same automaton over a finer alphabet, such as the refinement of
+    /// its own and another DFA's. Panics if a class of `alphabet` spans more
+    /// than one class of this DFA's.
+    pub fn with_alphabet(&self, alphabet: Alphabet) -> DFA {
+        let classes: Vec<usize> = (0..alphabet.len()).map(|c| {
+            let (lo, hi) = alphabet.range(c);
+            let class = self.alphabet.class_of(lo);
+            assert!(self.alphabet.class_of(hi) == class, "alphabet is not a refinement");
+            class
+        }).collect();
+        let mut transitions = vec![];
+        for s in 0..self.num_states() {
+            transitions.extend(classes.iter().map(|&c| self.next_class(s, c)));
+        }
+        DFA {
+            alphabet: alphabet,
+            transitions: transitions,
+            accepting: self.accepting.clone(),
+            rules: self.rules.clone(),
+            start_idx: self.start_idx,
+        }
+    }
+
+    /// The strings accepted by both automata. Like `difference`, this
+    /// works over the refinement of their alphabets and drops rule tags.
+    pub fn intersect(&self, other: &DFA) -> DFA {
+        self.combine(other, |x, y| x && y)
+    }
+
+    /// The strings accepted by this automaton but not by `other`
+    pub fn difference(&self, other: &DFA) -> DFA {
+        self.combine(other, |x, y| x && !y)
+    }
+
+    fn combine<F>(&self, other: &DFA, accept: F) -> DFA
+        where F: Fn(bool, bool) -> bool
+    {
+        if self.alphabet == other.alphabet {
+            return DFA::product(self, other, accept);
+        }
+        let alphabet = self.alphabet.refine(&other.alphabet);
+        DFA::product(&self.with_alphabet(alphabet.clone()), &other.with_alphabet(alphabet), accept)
+    }
+
+    /// The strings this automaton rejects. Every alphabet covers all of
+    /// `char`, so the missing transitions go to a new accepting sink state.
+    /// Rule tags are dropped.
+    pub fn complement(&self) -> DFA {
+        let width = self.alphabet.len();
+        let sink = self.num_states();
+        let mut dfa = DFA {
+            alphabet: self.alphabet.clone(),
+            transitions: self.transitions.iter().map(|t| Some(t.unwrap_or(sink))).collect(),
+            accepting: self.accepting.iter().map(|&a| !a).collect(),
+            rules: vec![None; sink],
+            start_idx: self.start_idx,
+        };
+        if self.transitions.iter().any(|t| t.is_none()) {
+            dfa.transitions.extend(vec![Some(sink); width]);
+            dfa.accepting.push(true);
+            dfa.rules.push(None);
+        }
+        dfa
+    }
+
+    /// A shortest accepted string, found by breadth first search. Each step
+    /// uses the first character of its class.
+    pub fn shortest_accepted(&self) -> Option<Vec<char>> {
+        // parent[s] is the state and class from which s was first reached
+        let mut parent: Vec<Option<(usize, usize)>> = vec![None; self.num_states()];
+        let mut seen = vec![false; self.num_states()];
+        let mut queue = VecDeque::new();
+        seen[self.start_idx] = true;
+        queue.push_back(self.start_idx);
+
+        while let Some(s) = queue.pop_front() {
+            if self.accepting[s] {
+                let mut xs = vec![];
+                let mut t = s;
+                while let Some((p, c)) = parent[t] {
+                    xs.push(self.alphabet.range(c).0);
+                    t = p;
+                }
+                xs.reverse();
+                return Some(xs);
+            }
+            for c in 0..self.alphabet.len() {
+                if let Some(t) = self.next_class(s, c) {
+                    if !seen[t] {
+                        seen[t] = true;
+                        parent[t] = Some((s, c));
+                        queue.push_back(t);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Hopcroft's partition refinement. Returns the minimal DFA for the same
+    /// language along with the state of the minimal DFA that each state of
+    /// this one was merged into. States from which no accepting state can be
+    /// reached are merged with the implicit dead state, so map to None.
+    /// States accepting different rules are never merged.
+    pub fn minimize(&self) -> (DFA, Vec<Option<usize>>) {
+        let width = self.alphabet.len();
+        // Complete the automaton with an explicit dead state
+        let dead = self.num_states();
+        let n = dead + 1;
+        let target = |s: usize, c: usize| {
+            if s == dead { dead } else { self.next_class(s, c).unwrap_or(dead) }
+        };
+
+        // inverse[c][t] lists the states with a transition to t on class c
+        let mut inverse = vec![vec![vec![]; n]; width];
+        for s in 0..n {
+            for c in 0..width {
+                inverse[c][target(s, c)].push(s);
+            }
+        }
+
+        // Initially states are distinguished only by what they accept
+        let mut initial: BTreeMap<(bool, Option<usize>), Vec<usize>> = BTreeMap::new();
+        for s in 0..n {
+            let key = if s == dead { (false, None) } else { (self.accepting[s], self.rules[s]) };
+            initial.entry(key).or_default().push(s);
+        }
+        let mut blocks = vec![];
+        let mut block_of = vec![0; n];
+        for (_, b) in initial {
+            for &s in b.iter() {
+                block_of[s] = blocks.len();
+            }
+            blocks.push(b);
+        }
+        // Splitting by all but one of the initial blocks has the same effect
+        // as splitting by all of them, so the largest can be left out
+        let largest = (0..blocks.len()).max_by_key(|&b| blocks[b].len()).unwrap();
+        let mut worklist: Vec<usize> = (0..blocks.len()).filter(|&b| b != largest).collect();
+
+        let mut marked = vec![false; n];
+        while let Some(a) = worklist.pop() {
+            let splitter = blocks[a].clone();
+            for into in inverse.iter() {
+                // The states with a transition into the splitter on this
+                // class, grouped by the block they're currently in
+                let mut touched: Vec<usize> = vec![];
+                for &t in splitter.iter() {
+                    for &s in into[t].iter() {
+                        if !marked[s] {
+                            marked[s] = true;
+                            touched.push(s);
+                        }
+                    }
+                }
+                let mut affected: Vec<usize> = touched.iter().map(|&s| block_of[s]).collect();
+                affected.sort();
+                affected.dedup();
+
+                for y in affected {
+                    let (inside, outside): (Vec<usize>, Vec<usize>) =
+                        blocks[y].iter().partition(|&&s| marked[s]);
+                    if outside.is_empty() {
+                        continue;
+                    }
+                    let z = blocks.len();
+                    let (keep, moved) = if inside.len() <= outside.len() {
+                        (outside, inside)
+                    } else {
+                        (inside, outside)
+                    };
+                    for &s in moved.iter() {
+                        block_of[s] = z;
+                    }
+                    blocks[y] = keep;
+                    blocks.push(moved);
+                    // If y is still to be processed both halves must be,
+                    // otherwise it suffices to process the smaller one.
+                    // Either way that means adding z.
+                    worklist.push(z);
+                }
+                for s in touched {
+                    marked[s] = false;
+                }
+            }
+        }
+
+        // Number the surviving blocks in order of their smallest state,
+        // dropping the dead block unless the start state is in it
+        let dead_block = block_of[dead];
+        let start_block = block_of[self.start_idx];
+        let mut new_ids: Vec<Option<usize>> = vec![None; blocks.len()];
+        let mut num_new = 0;
+        for &b in block_of[..dead].iter() {
+            if new_ids[b].is_none() && (b != dead_block || b == start_block) {
+                new_ids[b] = Some(num_new);
+                num_new += 1;
+            }
+        }
+
+        let mut min = DFA {
+            alphabet: self.alphabet.clone(),
+            transitions: vec![None; num_new * width],
+            accepting: vec![false; num_new],
+            rules: vec![None; num_new],
+            start_idx: new_ids[start_block].unwrap(),
+        };
+        for s in 0..dead {
+            if let Some(id) = new_ids[block_of[s]] {
+                min.accepting[id] = self.accepting[s];
+                min.rules[id] = self.rules[s];
+                for c in 0..width {
+                    let t = block_of[target(s, c)];
+                    if t != dead_block {
+                        min.transitions[id * width + c] = new_ids[t];
+                    }
+                }
+            }
+        }
+        let mapping = (0..dead)
+            .map(|s| if block_of[s] == dead_block { None } else { new_ids[block_of[s]] })
+            .collect();
+        (min, mapping)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::DFA;
+    use super::super::{Alphabet, NFA, Regex};
+    use super::super::test::strings;
+
+    fn assert_agrees(r: &Regex) {
+        let nfa = NFA::from_regex(r);
+        let dfa = DFA::from_nfa(&nfa);
+        for s in strings(&['a', 'b', 'c'], 5) {
+            assert_eq!(nfa.accepts(&s), dfa.accepts(&s), "{:?} on {:?}", r, s);
+        }
+    }
+
+    #[test]
+    fn test_dfa_agrees_with_nfa() {
+        let a = Regex::Single('a');
+        let b = Regex::Single('b');
+        let c = Regex::Single('c');
+
+        assert_agrees(&a);
+        assert_agrees(&a.or(&b).or(&c));
+        assert_agrees(&a.then(&b));
+        assert_agrees(&a.or(&b).star());
+        assert_agrees(&Regex::Empty);
+        assert_agrees(&Regex::Nothing);
+        assert_agrees(&Regex::parse("(a|b)*abb").unwrap());
+        assert_agrees(&Regex::parse("[^b]+b?").unwrap());
+        assert_agrees(&Regex::parse("(ab|a)(bc|c)*").unwrap());
+    }
+
+    #[test]
+    fn test_dfa_states() {
+        let r = Regex::parse("(a|b)*abb").unwrap();
+        let dfa = DFA::from_nfa(&NFA::from_regex(&r));
+
+        // the textbook subset construction for this pattern gives five states
+        assert_eq!(dfa.num_states(), 5);
+        assert!(dfa.accepts(&['a', 'a', 'b', 'b']));
+        assert!(!dfa.accepts(&['a', 'b', 'b', 'x']));
+    }
+
+    fn assert_minimizes_to(r: &Regex, num_states: usize) {
+        let nfa = NFA::from_regex(r);
+        let dfa = DFA::from_nfa(&nfa);
+        let (min, mapping) = dfa.minimize();
+
+        assert_eq!(min.num_states(), num_states, "{:?}", r);
+        assert_eq!(mapping.len(), dfa.num_states());
+        assert_eq!(mapping[dfa.start()].unwrap_or(min.start()), min.start());
+        for s in strings(&['a', 'b', 'c'], 5) {
+            assert_eq!(nfa.accepts(&s), min.accepts(&s), "{:?} on {:?}", r, s);
+        }
+        // minimizing again changes nothing
+        assert_eq!(min.minimize().0.num_states(), num_states);
+    }
+
+    #[test]
+    fn test_dfa_minimize() {
+        let a = Regex::Single('a');
+        let b = Regex::Single('b');
+
+        assert_minimizes_to(&a.or(&b).star(), 1);
+        assert_minimizes_to(&a.or(&b).star().star(), 1);
+        assert_minimizes_to(&Regex::parse("(a|b)*abb").unwrap(), 4);
+        assert_minimizes_to(&Regex::parse("a*|a*a").unwrap(), 1);
+        assert_minimizes_to(&Regex::parse("ab|ac|a(b|c)").unwrap(), 3);
+        assert_minimizes_to(&Regex::parse("(a|b)(a|b)(a|b)").unwrap(), 4);
+        assert_minimizes_to(&Regex::Empty, 1);
+        assert_minimizes_to(&Regex::Nothing, 1);
+        assert_minimizes_to(&a.then(&Regex::Nothing), 1);
+    }
+
+    #[test]
+    fn test_dfa_minimize_mapping() {
+        let dfa = DFA::from_nfa(&NFA::from_regex(&Regex::parse("ab|cb").unwrap()));
+        let (min, mapping) = dfa.minimize();
+
+        assert_eq!(min.num_states(), 3);
+        // the states after 'a' and after 'c' are merged
+        let after_a = dfa.next(dfa.start(), 'a').unwrap();
+        let after_c = dfa.next(dfa.start(), 'c').unwrap();
+        assert_ne!(after_a, after_c);
+        assert_eq!(mapping[after_a], mapping[after_c]);
+        assert_eq!(mapping[after_a], min.next(min.start(), 'a'));
+    }
+
+    #[test]
+    fn test_dfa_product() {
+        let a = NFA::from_regex(&Regex::parse("a*b").unwrap());
+        let b = NFA::from_regex(&Regex::parse("(a|b)(a|b)").unwrap());
+        let alphabet = Alphabet::from_nfas(&[&a, &b]);
+        let da = DFA::from_nfa_with_alphabet(&a, alphabet.clone());
+        let db = DFA::from_nfa_with_alphabet(&b, alphabet);
+
+        let both = DFA::product(&da, &db, |x, y| x && y);
+        let either = DFA::product(&da, &db, |x, y| x || y);
+        let neither = DFA::product(&da, &db, |x, y| !x && !y);
+        for s in strings(&['a', 'b', 'c'], 5) {
+            assert_eq!(both.accepts(&s), a.accepts(&s) && b.accepts(&s));
+            assert_eq!(either.accepts(&s), a.accepts(&s) || b.accepts(&s));
+            assert_eq!(neither.accepts(&s), !a.accepts(&s) && !b.accepts(&s));
+        }
+    }
+
+    #[test]
+    fn test_dfa_language_operations() {
+        // built separately, so over different alphabets
+        let a = NFA::from_regex(&Regex::parse("a*b|[c-d]").unwrap());
+        let b = NFA::from_regex(&Regex::parse("[a-b][b-c]").unwrap());
+        let (da, db) = (DFA::from_nfa(&a), DFA::from_nfa(&b));
+        assert!(da.alphabet() != db.alphabet());
+
+        let both = da.intersect(&db);
+        let only_a = da.difference(&db);
+        let not_a = da.complement();
+        for s in strings(&['a', 'b', 'c', 'd'], 4) {
+            assert_eq!(both.accepts(&s), a.accepts(&s) && b.accepts(&s), "{:?}", s);
+            assert_eq!(only_a.accepts(&s), a.accepts(&s) && !b.accepts(&s), "{:?}", s);
+            assert_eq!(not_a.accepts(&s), !a.accepts(&s), "{:?}", s);
+        }
+        assert_eq!(both.shortest_accepted(), Some(vec!['a', 'b']));
+        assert_eq!(only_a.shortest_accepted(), Some(vec!['b']));
+        assert_eq!(not_a.shortest_accepted(), Some(vec![]));
+        assert_eq!(not_a.complement().minimize().0.num_states(), da.minimize().0.num_states());
+        assert_eq!(da.difference(&da).shortest_accepted(), None);
+    }
+
+    #[test]
+    fn test_dfa_with_alphabet() {
+        let a = NFA::from_regex(&Regex::parse("[a-z]+").unwrap());
+        let b = NFA::from_regex(&Regex::parse("m").unwrap());
+        let da = DFA::from_nfa(&a);
+        let finer = da.with_alphabet(Alphabet::from_nfas(&[&a, &b]));
+
+        assert_eq!(finer.alphabet().len(), da.alphabet().len() + 2);
+        for s in strings(&['a', 'm', 'z', '0'], 3) {
+            assert_eq!(finer.accepts(&s), da.accepts(&s));
+        }
+    }
+
+    #[test]
+    fn test_dfa_shortest_accepted() {
+        let shortest = |pattern: &str| {
+            DFA::from_nfa(&NFA::from_regex(&Regex::parse(pattern).unwrap())).shortest_accepted()
+        };
+
+        assert_eq!(shortest("(a|b)*abb"), Some(vec!['a', 'b', 'b']));
+        assert_eq!(shortest("a*"), Some(vec![]));
+        assert_eq!(shortest("xyz|[p-r]"), Some(vec!['p']));
+        assert_eq!(shortest("a[]"), None);
+    }
+
+    #[test]
+    fn test_dfa_rules() {
+        let kw = NFA::from_regex(&Regex::parse("if").unwrap()).tagged(0);
+        let ident = NFA::from_regex(&Regex::parse("[a-z]+").unwrap()).tagged(1);
+        let dfa = DFA::from_nfa(&NFA::union(&[kw, ident]));
+        let run = |dfa: &DFA, xs: &[char]| {
+            let mut s = dfa.start();
+            for &c in xs {
+                s = dfa.next(s, c).unwrap();
+            }
+            dfa.rule(s)
+        };
+
+        assert_eq!(run(&dfa, &['i', 'f']), Some(0));
+        assert_eq!(run(&dfa, &['i']), Some(1));
+        assert_eq!(run(&dfa, &['i', 'f', 'f']), Some(1));
+
+        // the states after "if" and "iff" accept the same strings but must
+        // stay distinct as they accept different rules
+        let (min, _) = dfa.minimize();
+        assert_eq!(min.num_states(), 4);
+        assert_eq!(run(&min, &['i', 'f']), Some(0));
+        assert_eq!(run(&min, &['i', 'f', 'f']), Some(1));
+    }
+
+    #[test]
+    fn test_dfa_classes() {
+        let r = Regex::parse("[a-z][a-z0-9]*").unwrap();
+        let dfa = DFA::from_nfa(&NFA::from_regex(&r));
+
+        assert!(dfa.accepts(&['x', '1', 'y']));
+        assert!(!dfa.accepts(&['1', 'x']));
+        assert!(!dfa.accepts(&['x', 'Y']));
+        assert!(!dfa.accepts(&[]));
+    }
+}
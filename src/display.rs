@@ -0,0 +1,243 @@
+use std::fmt;
+
+use super::Regex;
+
+/// How tightly an operand must bind to be printed without parentheses
+const ALTERNATION: u8 = 0;
+const INTERSECTION: u8 = 1;
+const CONCATENATION: u8 = 2;
+const COMPLEMENT: u8 = 3;
+const REPETITION: u8 = 4;
+
+/// Prints the concrete syntax accepted by `Regex::parse`, using as few
+/// parentheses as the precedence of star over complement over concatenation
+/// over intersection over alternation allows. Parsing the output gives back
+/// an equal tree, provided each group number appears once and in order of
+/// the opening parentheses, as in any pattern without a repeated group.
+/// `Nothing` has no syntax of its own, and is printed as the empty class
+/// `[]`.
+impl fmt::Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, ALTERNATION)
+    }
+}
+
+impl Regex {
+
+    /// Writes this regex where it must bind at least as tightly as `level`,
+    /// adding a non-capturing group if it does not
+    fn write(&self, f: &mut fmt::Formatter, level: u8) -> fmt::Result {
+        let binds = match *self {
+            Regex::Or(ref r, ref s) if **s == Regex::Empty => return write_postfix(f, r, "?"),
+            Regex::Then(ref r, ref s) if is_star_of(s, r) => return write_postfix(f, r, "+"),
+            Regex::Or(_, _) => ALTERNATION,
+            Regex::And(_, _) => INTERSECTION,
+            Regex::Then(_, _) => CONCATENATION,
+            Regex::Not(_) => COMPLEMENT,
+            // an empty alternative or intersected operand is written as nothing at all
+            Regex::Empty if level <= INTERSECTION => return Ok(()),
+            _ => REPETITION,
+        };
+        if binds < level {
+            f.write_str("(?:")?;
+            self.write(f, ALTERNATION)?;
+            return f.write_str(")");
+        }
+
+        match *self {
+            Regex::Nothing => f.write_str("[]"),
+            Regex::Empty => f.write_str("(?:)"),
+            Regex::Single(c) => write_char(f, c, "\\|&~*+?{()[."),
+            Regex::Class(ref k) if k.is_negated() && k.raw_ranges().is_empty() => f.write_str("."),
+            Regex::Class(ref k) => {
+                f.write_str(if k.is_negated() { "[^" } else { "[" })?;
+                for &(lo, hi) in k.raw_ranges() {
+                    write_char(f, lo, "\\]-^")?;
+                    if lo != hi {
+                        f.write_str("-")?;
+                        write_char(f, hi, "\\]-^")?;
+                    }
+                }
+                f.write_str("]")
+            },
+            Regex::Or(ref r, ref s) => {
+                r.write(f, ALTERNATION)?;
+                f.write_str("|")?;
+                s.write(f, INTERSECTION)
+            },
+            Regex::And(ref r, ref s) => {
+                r.write(f, INTERSECTION)?;
+                f.write_str("&")?;
+                s.write(f, CONCATENATION)
+            },
+            Regex::Then(ref r, ref s) => {
+                r.write(f, CONCATENATION)?;
+                s.write(f, COMPLEMENT)
+            },
+            Regex::Not(ref r) => {
+                f.write_str("~")?;
+                r.write(f, COMPLEMENT)
+            },
+            Regex::Star(ref r) => write_postfix(f, r, "*"),
+            Regex::Capture(_, ref r) => {
+                f.write_str("(")?;
+                r.write(f, ALTERNATION)?;
+                f.write_str(")")
+            },
+        }
+    }
+}
+
+/// Whether `s` is `r*`, so that `rs` can be written `r+`
+fn is_star_of(s: &Regex, r: &Regex) -> bool {
+    match *s {
+        Regex::Star(ref t) => **t == *r,
+        _ => false,
+    }
+}
+
+/// Writes `r` followed by a repetition operator, which binds tighter than
+/// anything but a group or another repetition
+fn write_postfix(f: &mut fmt::Formatter, r: &Regex, op: &str) -> fmt::Result {
+    r.write(f, REPETITION)?;
+    f.write_str(op)
+}
+
+/// Writes `c`, escaped if it is one of `special`, and as an escape sequence
+/// if it is a control character that `parse` has one for
+fn write_char(f: &mut fmt::Formatter, c: char, special: &str) -> fmt::Result {
+    match c {
+        '\n' => f.write_str("\\n"),
+        '\t' => f.write_str("\\t"),
+        '\r' => f.write_str("\\r"),
+        '\0' => f.write_str("\\0"),
+        c if special.contains(c) => write!(f, "\\{}", c),
+        c => write!(f, "{}", c),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::super::Regex;
+
+    fn round_trip(r: &Regex) {
+        let printed = r.to_string();
+        let parsed = Regex::parse(&printed);
+        assert_eq!(parsed.as_ref(), Ok(r), "{:?} printed as {:?}", r, printed);
+    }
+
+    fn prints_as(pattern: &str, expected: &str) {
+        let r = Regex::parse(pattern).unwrap();
+        assert_eq!(r.to_string(), expected);
+        round_trip(&r);
+    }
+
+    /// All trees over a few atoms with at most `depth` nested operators
+    fn trees(depth: usize) -> Vec<Regex> {
+        let mut rs = vec![Regex::Empty, Regex::Single('a'), Regex::Single('|'), Regex::any(),
+                          Regex::class(&[('a', 'c'), ('-', '-')], true)];
+        if depth > 0 {
+            let smaller = trees(depth - 1);
+            for r in smaller.iter() {
+                rs.push(r.star());
+                rs.push(r.not());
+                for s in smaller.iter() {
+                    rs.push(r.or(s));
+                    rs.push(r.then(s));
+                    rs.push(r.and(s));
+                }
+            }
+        }
+        rs
+    }
+
+    #[test]
+    fn test_display_minimal_parentheses() {
+        prints_as("a|b|c", "a|b|c");
+        prints_as("a|(?:b|c)", "a|(?:b|c)");
+        prints_as("abc", "abc");
+        prints_as("a(?:bc)", "a(?:bc)");
+        prints_as("(?:ab|c)d", "(?:ab|c)d");
+        prints_as("ab*|c", "ab*|c");
+        prints_as("(?:ab)*", "(?:ab)*");
+        prints_as("a**", "a**");
+        prints_as("(?:a*)", "a*");
+    }
+
+    #[test]
+    fn test_display_sugar() {
+        prints_as("a+b?", "a+b?");
+        prints_as("(?:ab)+", "(?:ab)+");
+        prints_as("a|", "a?");
+        prints_as("a{2,}", "aaa*");
+        prints_as("a(?:aa*)", "aa+");
+        prints_as("a{0,2}", "(?:aa?)?");
+        prints_as("(a)+", "(a)+");
+    }
+
+    #[test]
+    fn test_display_empty() {
+        prints_as("", "");
+        prints_as("|a", "|a");
+        prints_as("a(?:)", "a(?:)");
+        prints_as("(?:)*", "(?:)*");
+        prints_as("()", "()");
+    }
+
+    #[test]
+    fn test_display_escapes() {
+        prints_as("\\(\\)\\[\\]\\{\\}\\*\\+\\?\\|\\.\\\\", "\\(\\)\\[]\\{}\\*\\+\\?\\|\\.\\\\");
+        prints_as("\\n\\t\\r\\0n", "\\n\\t\\r\\0n");
+        prints_as("é", "é");
+    }
+
+    #[test]
+    fn test_display_classes() {
+        prints_as("[a-z_]", "[a-z_]");
+        prints_as("[^0-9]", "[^0-9]");
+        prints_as("[-a-]", "[\\-a\\-]");
+        prints_as("[\\]^\\\\]", "[\\]\\^\\\\]");
+        prints_as("[]", "[]");
+        prints_as(".", ".");
+        prints_as("[^]", ".");
+        prints_as("[\\n-\\r]", "[\\n-\\r]");
+    }
+
+    #[test]
+    fn test_display_groups() {
+        prints_as("(a)|(b)", "(a)|(b)");
+        prints_as("((a)b)*", "((a)b)*");
+        prints_as("(?:(a)|b)(b)", "(?:(a)|b)(b)");
+        prints_as("(a|b)c", "(a|b)c");
+    }
+
+    #[test]
+    fn test_display_intersection_complement() {
+        prints_as("a|b&c", "a|b&c");
+        prints_as("(?:a|b)&c", "(?:a|b)&c");
+        prints_as("a&(?:b&c)", "a&(?:b&c)");
+        prints_as("ab&cd", "ab&cd");
+        prints_as("a(?:b&c)", "a(?:b&c)");
+        prints_as("~ab", "~ab");
+        prints_as("~(?:ab)", "~(?:ab)");
+        prints_as("a~b*", "a~b*");
+        prints_as("(?:~a)*", "(?:~a)*");
+        prints_as("~~a", "~~a");
+        prints_as("&a", "&a");
+        prints_as("\\&\\~", "\\&\\~");
+    }
+
+    #[test]
+    fn test_display_nothing() {
+        assert_eq!(Regex::Nothing.to_string(), "[]");
+        assert_eq!(Regex::Single('a').or(&Regex::Nothing).star().to_string(), "(?:a|[])*");
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        for r in trees(2) {
+            round_trip(&r);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::fmt::Write;
+
+use super::{CharClass, NFA, Regex};
+
+/// Graphviz output, for looking at constructions with
+///
+///     dot -Tpng -o nfa.png
+impl NFA {
+
+    /// The states and transitions of this NFA in DOT format. States are
+    /// labelled with their index, and with their rule or capture slot if they
+    /// have one. Epsilon transitions are labelled ε.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        out.push_str("digraph NFA {\n");
+        out.push_str("    rankdir=LR;\n");
+        out.push_str("    node [shape=circle];\n");
+        out.push_str("    start [shape=point];\n");
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            let mut label = i.to_string();
+            if let Some(slot) = node.save {
+                write!(label, "\\nsave {}", slot).unwrap();
+            }
+            match self.accepting.get(&i) {
+                Some(&Some(rule)) => {
+                    write!(label, "\\nrule {}", rule).unwrap();
+                    writeln!(out, "    {} [shape=doublecircle, label=\"{}\"];", i, label).unwrap();
+                },
+                Some(&None) => writeln!(out, "    {} [shape=doublecircle, label=\"{}\"];", i, label).unwrap(),
+                None => writeln!(out, "    {} [label=\"{}\"];", i, label).unwrap(),
+            }
+        }
+
+        writeln!(out, "    start -> {};", self.start_idx).unwrap();
+        for (i, node) in self.nodes.iter().enumerate() {
+            for &(label, j) in node.transitions.iter() {
+                let label = match label {
+                    None => "ε".to_string(),
+                    Some((lo, hi)) if lo == hi => escape(lo),
+                    Some((lo, hi)) => format!("{}-{}", escape(lo), escape(hi)),
+                };
+                writeln!(out, "    {} -> {} [label=\"{}\"];", i, j, label).unwrap();
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+impl Regex {
+
+    /// The syntax tree of this regex in DOT format, with each operator
+    /// pointing at its operands from left to right
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        out.push_str("digraph Regex {\n");
+        out.push_str("    node [shape=box];\n");
+        let mut next = 0;
+        self.dot_nodes(&mut out, &mut next);
+        out.push_str("}\n");
+        out
+    }
+
+    /// Writes the nodes for this subtree, numbered in preorder from `next`,
+    /// and the edges to them. Returns the number given to this node.
+    fn dot_nodes(&self, out: &mut String, next: &mut usize) -> usize {
+        let id = *next;
+        *next += 1;
+        let label = match *self {
+            Regex::Nothing => "∅".to_string(),
+            Regex::Empty => "ε".to_string(),
+            Regex::Single(c) => format!("'{}'", escape(c)),
+            Regex::Class(ref k) => class_label(k),
+            Regex::Or(_, _) => "|".to_string(),
+            Regex::Then(_, _) => "·".to_string(),
+            Regex::Star(_) => "*".to_string(),
+            Regex::Capture(g, _) => format!("group {}", g),
+            Regex::And(_, _) => "&".to_string(),
+            Regex::Not(_) => "~".to_string(),
+        };
+        writeln!(out, "    {} [label=\"{}\"];", id, label).unwrap();
+
+        let children: Vec<&Regex> = match *self {
+            Regex::Nothing | Regex::Empty | Regex::Single(_) | Regex::Class(_) => vec![],
+            Regex::Or(ref r, ref s) | Regex::Then(ref r, ref s) | Regex::And(ref r, ref s) => vec![r, s],
+            Regex::Star(ref r) | Regex::Capture(_, ref r) | Regex::Not(ref r) => vec![r],
+        };
+        for child in children {
+            let child_id = child.dot_nodes(out, next);
+            writeln!(out, "    {} -> {};", id, child_id).unwrap();
+        }
+        id
+    }
+}
+
+fn class_label(k: &CharClass) -> String {
+    let mut label = String::from("[");
+    if k.is_negated() {
+        label.push('^');
+    }
+    for &(lo, hi) in k.raw_ranges() {
+        label.push_str(&escape(lo));
+        if lo != hi {
+            label.push('-');
+            label.push_str(&escape(hi));
+        }
+    }
+    label.push(']');
+    label
+}
+
+/// `c` as it should appear inside a quoted DOT label, with control
+/// characters written as escapes rather than taking effect
+fn escape(c: char) -> String {
+    let s: String = if c.is_control() { c.escape_default().collect() } else { c.to_string() };
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::super::{NFA, Regex};
+
+    #[test]
+    fn test_nfa_to_dot() {
+        let dot = NFA::from_regex(&Regex::parse("a|[b-d]").unwrap()).to_dot();
+
+        assert!(dot.starts_with("digraph NFA {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("start [shape=point];"));
+        assert!(dot.contains("label=\"ε\""));
+        assert!(dot.contains("label=\"a\""));
+        assert!(dot.contains("label=\"b-d\""));
+        assert_eq!(dot.matches("doublecircle").count(), 1);
+    }
+
+    #[test]
+    fn test_nfa_to_dot_exact() {
+        let dot = NFA::single('"').to_dot();
+        assert_eq!(dot, "digraph NFA {\n    \
+                         rankdir=LR;\n    \
+                         node [shape=circle];\n    \
+                         start [shape=point];\n    \
+                         0 [label=\"0\"];\n    \
+                         1 [shape=doublecircle, label=\"1\"];\n    \
+                         start -> 0;\n    \
+                         0 -> 1 [label=\"\\\"\"];\n\
+                         }\n");
+    }
+
+    #[test]
+    fn test_nfa_to_dot_rules() {
+        let n = NFA::union(&[NFA::single('a').tagged(0), NFA::single('\n').tagged(1)]);
+        let dot = n.to_dot();
+
+        assert!(dot.contains("\\nrule 0\""));
+        assert!(dot.contains("\\nrule 1\""));
+        assert!(dot.contains("label=\"\\\\n\""));
+    }
+
+    #[test]
+    fn test_regex_to_dot() {
+        let dot = Regex::parse("(a|[^x])*").unwrap().to_dot();
+        assert_eq!(dot, "digraph Regex {\n    \
+                         node [shape=box];\n    \
+                         0 [label=\"*\"];\n    \
+                         1 [label=\"group 1\"];\n    \
+                         2 [label=\"|\"];\n    \
+                         3 [label=\"'a'\"];\n    \
+                         2 -> 3;\n    \
+                         4 [label=\"[^x]\"];\n    \
+                         2 -> 4;\n    \
+                         1 -> 2;\n    \
+                         0 -> 1;\n\
+                         }\n");
+    }
+}
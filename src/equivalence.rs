@@ -0,0 +1,77 @@
+use super::{Alphabet, DFA, NFA, Regex};
+
+/// Deciding relationships between the languages of two regexes, by
+/// searching the product of their DFAs for a distinguishing string
+impl Regex {
+
+    /// Whether both regexes match exactly the same strings. If not, returns a
+    /// shortest string matched by one but not the other.
+    pub fn equivalent(&self, other: &Regex) -> Result<(), Vec<char>> {
+        self.distinguish(other, |x, y| x != y)
+    }
+
+    /// Whether every string matched by this regex is matched by `other`. If
+    /// not, returns a shortest string matched by this one but not `other`.
+    pub fn is_subset_of(&self, other: &Regex) -> Result<(), Vec<char>> {
+        self.distinguish(other, |x, y| x && !y)
+    }
+
+    /// A shortest string on whose acceptance by each regex `bad` holds
+    fn distinguish<F>(&self, other: &Regex, bad: F) -> Result<(), Vec<char>>
+        where F: Fn(bool, bool) -> bool
+    {
+        let a = NFA::from_regex(self);
+        let b = NFA::from_regex(other);
+        let alphabet = Alphabet::from_nfas(&[&a, &b]);
+        let da = DFA::from_nfa_with_alphabet(&a, alphabet.clone());
+        let db = DFA::from_nfa_with_alphabet(&b, alphabet);
+        match DFA::product(&da, &db, bad).shortest_accepted() {
+            Some(xs) => Err(xs),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::super::{NFA, Regex};
+
+    fn parse(pattern: &str) -> Regex {
+        Regex::parse(pattern).unwrap()
+    }
+
+    #[test]
+    fn test_equivalent() {
+        assert_eq!(parse("(a|b)*").equivalent(&parse("(a*b*)*")), Ok(()));
+        assert_eq!(parse("a(ba)*").equivalent(&parse("(ab)*a")), Ok(()));
+        assert_eq!(parse("[a-c]").equivalent(&parse("a|b|c")), Ok(()));
+        assert_eq!(parse("a{2,}").equivalent(&parse("aaa*")), Ok(()));
+        assert_eq!(parse("[]").equivalent(&Regex::Nothing), Ok(()));
+        assert_eq!(parse("[]*").equivalent(&Regex::Empty), Ok(()));
+    }
+
+    #[test]
+    fn test_not_equivalent() {
+        assert_eq!(parse("a*").equivalent(&parse("a+")), Err(vec![]));
+        assert_eq!(parse("(a|b)*abb").equivalent(&parse("(a|b)*bb")), Err(vec!['b', 'b']));
+        assert_eq!(parse("[a-z]").equivalent(&parse("[a-y]")), Err(vec!['z']));
+
+        let r = parse("(ab|ba)*");
+        let s = parse("(a|b)*");
+        let w = r.equivalent(&s).unwrap_err();
+        assert_eq!(w.len(), 1);
+        assert!(NFA::from_regex(&s).accepts(&w));
+        assert!(!NFA::from_regex(&r).accepts(&w));
+    }
+
+    #[test]
+    fn test_is_subset_of() {
+        assert_eq!(parse("ab").is_subset_of(&parse("a*b*")), Ok(()));
+        assert_eq!(parse("(ab)*").is_subset_of(&parse("(a|b)*")), Ok(()));
+        assert_eq!(Regex::Nothing.is_subset_of(&parse("a")), Ok(()));
+        assert_eq!(parse("a*b*").is_subset_of(&parse("ab")), Err(vec![]));
+        assert_eq!(parse("a+b*").is_subset_of(&parse("ab*")), Err(vec!['a', 'a']));
+        assert_eq!(parse("a|bc").is_subset_of(&Regex::Nothing), Err(vec!['a']));
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+
+use super::{Alphabet, DFA, NFA, Regex, Rng};
+use super::class::{nth_from, range_size};
+
+/// How many mutated strings `Generator::rejected` tries before giving up
+const MUTATION_ATTEMPTS: usize = 100;
+
+/// Generates random strings a regex matches, and near misses it does not,
+/// for property testing. Matching strings are built by walking the regex:
+/// choosing a branch of each alternation, repeating each star a random number
+/// of times and drawing each class character uniformly from its members.
+#[derive(Debug,Clone)]
+pub struct Generator {
+    rng: Rng,
+    /// Stars nested deeper than this repeat their operand no times
+    max_star_depth: usize,
+    /// The most times any star repeats its operand
+    max_repeat: usize,
+}
+
+impl Generator {
+
+    /// A generator drawing from `rng`, in which stars nested within more
+    /// than `max_star_depth` others repeat nothing and the rest repeat their
+    /// operand up to three times
+    pub fn new(rng: Rng, max_star_depth: usize) -> Generator {
+        Generator {
+            rng: rng,
+            max_star_depth: max_star_depth,
+            max_repeat: 3,
+        }
+    }
+
+    /// Sets the most times any star repeats its operand
+    pub fn set_max_repeat(&mut self, max_repeat: usize) {
+        self.max_repeat = max_repeat;
+    }
+
+    /// A random string `r` matches, or None if it matches nothing
+    pub fn matching(&mut self, r: &Regex) -> Option<Vec<char>> {
+        self.matching_with(r, &mut HashMap::new())
+    }
+
+    /// As `matching`, sampling the intersections and complements in `r`
+    /// from the DFAs in `dfas`, and adding any not there yet
+    fn matching_with<'r>(&mut self, r: &'r Regex, dfas: &mut HashMap<&'r Regex, DFA>) -> Option<Vec<char>> {
+        let mut xs = vec![];
+        if self.walk(r, 0, &mut xs, dfas) { Some(xs) } else { None }
+    }
+
+    /// A random string `r` does not match, made by inserting, deleting or
+    /// replacing a character of a string it does. Inserted characters are
+    /// the first or last of a class of characters `r` does not distinguish,
+    /// as those are the likeliest to be near misses. Returns None if every
+    /// attempt was still matched, as happens when `r` matches almost
+    /// everything.
+    pub fn rejected(&mut self, r: &Regex) -> Option<Vec<char>> {
+        let nfa = NFA::from_regex(r);
+        let alphabet = Alphabet::from_nfa(&nfa);
+        let edges: Vec<char> = (0..alphabet.len()).flat_map(|i| {
+            let (lo, hi) = alphabet.range(i);
+            vec![lo, hi]
+        }).collect();
+
+        let mut dfas = HashMap::new();
+        for _ in 0..MUTATION_ATTEMPTS {
+            // if r matches nothing, any string will do
+            let mut xs = self.matching_with(r, &mut dfas).unwrap_or_default();
+            let c = *self.rng.choose(&edges);
+            match self.rng.below(3) {
+                0 => {
+                    let i = self.rng.below(xs.len() + 1);
+                    xs.insert(i, c);
+                },
+                1 if !xs.is_empty() => {
+                    let i = self.rng.below(xs.len());
+                    xs.remove(i);
+                },
+                _ if !xs.is_empty() => {
+                    let i = self.rng.below(xs.len());
+                    xs[i] = c;
+                },
+                _ => xs.push(c),
+            }
+            if !nfa.accepts(&xs) {
+                return Some(xs);
+            }
+        }
+        None
+    }
+
+    /// Appends to `xs` a string `r` matches, within `depth` stars. Returns
+    /// false, leaving junk in `xs`, exactly when `r` matches nothing, which
+    /// does not depend on the choices made along the way.
+    fn walk<'r>(&mut self, r: &'r Regex, depth: usize, xs: &mut Vec<char>,
+                dfas: &mut HashMap<&'r Regex, DFA>) -> bool {
+        match *r {
+            Regex::Nothing => false,
+            Regex::Empty => true,
+            Regex::Single(c) => {
+                xs.push(c);
+                true
+            },
+            Regex::Class(ref k) => {
+                let ranges = k.ranges();
+                let total: u64 = ranges.iter().map(|&(lo, hi)| range_size(lo, hi) as u64).sum();
+                if total == 0 {
+                    return false;
+                }
+                let mut i = self.rng.next_u64() % total;
+                for &(lo, hi) in ranges.iter() {
+                    let size = range_size(lo, hi) as u64;
+                    if i < size {
+                        xs.push(nth_from(lo, i as u32));
+                        break;
+                    }
+                    i -= size;
+                }
+                true
+            },
+            Regex::Or(ref a, ref b) => {
+                let (first, second) = if self.rng.below(2) == 0 { (a, b) } else { (b, a) };
+                let mark = xs.len();
+                if self.walk(first, depth, xs, dfas) {
+                    return true;
+                }
+                xs.truncate(mark);
+                self.walk(second, depth, xs, dfas)
+            },
+            Regex::Then(ref a, ref b) => self.walk(a, depth, xs, dfas) && self.walk(b, depth, xs, dfas),
+            Regex::Star(ref a) => {
+                if depth < self.max_star_depth {
+                    for _ in 0..self.rng.below(self.max_repeat + 1) {
+                        let mark = xs.len();
+                        if !self.walk(a, depth + 1, xs, dfas) {
+                            // only the empty string repeats something matching nothing
+                            xs.truncate(mark);
+                            break;
+                        }
+                    }
+                }
+                true
+            },
+            Regex::Capture(_, ref a) => self.walk(a, depth, xs, dfas),
+            Regex::And(_, _) | Regex::Not(_) => self.sample(r, xs, dfas),
+        }
+    }
+
+    /// Appends a string `r` matches, drawn from its DFA, for intersections
+    /// and complements, which have no structure to walk. The DFA is built
+    /// the first time `r` is sampled and kept in `dfas`, as the subset
+    /// construction can take exponential time.
+    fn sample<'r>(&mut self, r: &'r Regex, xs: &mut Vec<char>, dfas: &mut HashMap<&'r Regex, DFA>) -> bool {
+        let dfa = dfas.entry(r).or_insert_with(|| DFA::from_nfa(&NFA::from_regex(r)));
+        let len = self.rng.below(self.max_repeat + 1);
+        match dfa.sample_accepted(len, &mut self.rng).or_else(|| dfa.shortest_accepted()) {
+            Some(ys) => {
+                xs.extend(ys);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::Generator;
+    use super::super::{NFA, Regex, Rng};
+
+    const PATTERNS: &[&str] = &[
+        "a*b", "(?:ab|c)+d?", "[a-c]{2,4}", "[^a-y]x", ".", "(a)(b|(c))*",
+        "x[]|y", "(?:[]a)*z", "[a-c]*&~(?:.*ab.*)", "~(?:a*)", "(?:(?:a|b)*c)*",
+    ];
+
+    /// A random regex over `a`, `b` and `c` with at most `depth` nested operators
+    fn random_regex(rng: &mut Rng, depth: usize) -> Regex {
+        if depth == 0 || rng.below(4) == 0 {
+            return match rng.below(6) {
+                0 => Regex::Nothing,
+                1 => Regex::Empty,
+                2 => Regex::class(&[('a', 'b')], rng.below(2) == 0),
+                _ => Regex::Single(*rng.choose(&['a', 'b', 'c'])),
+            };
+        }
+        match rng.below(8) {
+            0 | 1 => random_regex(rng, depth - 1).into_or(random_regex(rng, depth - 1)),
+            2 | 3 => random_regex(rng, depth - 1).into_then(random_regex(rng, depth - 1)),
+            4 | 5 => random_regex(rng, depth - 1).into_star(),
+            6 => random_regex(rng, depth - 1).into_and(random_regex(rng, depth - 1)),
+            _ => random_regex(rng, depth - 1).into_not(),
+        }
+    }
+
+    #[test]
+    fn test_generate_matching() {
+        let mut g = Generator::new(Rng::new(25), 3);
+        for pattern in PATTERNS {
+            let r = Regex::parse(pattern).unwrap();
+            let n = NFA::from_regex(&r);
+            for _ in 0..50 {
+                let xs = g.matching(&r).unwrap();
+                assert!(n.accepts(&xs), "{} on {:?}", pattern, xs);
+            }
+        }
+        assert_eq!(g.matching(&Regex::parse("a[]").unwrap()), None);
+        assert_eq!(g.matching(&Regex::parse("a&b").unwrap()), None);
+    }
+
+    #[test]
+    fn test_generate_star_depth() {
+        let r = Regex::parse("(?:a*b)*").unwrap();
+        let mut g = Generator::new(Rng::new(1), 0);
+        for _ in 0..20 {
+            assert_eq!(g.matching(&r), Some(vec![]));
+        }
+
+        let mut g = Generator::new(Rng::new(1), 1);
+        g.set_max_repeat(2);
+        let mut lengths = [false; 3];
+        for _ in 0..50 {
+            let xs = g.matching(&r).unwrap();
+            assert!(xs.iter().all(|&c| c == 'b'), "{:?}", xs);
+            lengths[xs.len()] = true;
+        }
+        assert!(lengths.iter().all(|&l| l));
+    }
+
+    #[test]
+    fn test_generate_reproducible() {
+        let r = Regex::parse("(?:[a-z]|[0-9]+)*").unwrap();
+        let mut g = Generator::new(Rng::new(9), 2);
+        let mut h = g.clone();
+        for _ in 0..20 {
+            assert_eq!(g.matching(&r), h.matching(&r));
+            assert_eq!(g.rejected(&r), h.rejected(&r));
+        }
+    }
+
+    #[test]
+    fn test_generate_rejected() {
+        let mut g = Generator::new(Rng::new(25), 3);
+        for pattern in PATTERNS {
+            let r = Regex::parse(pattern).unwrap();
+            let n = NFA::from_regex(&r);
+            for _ in 0..20 {
+                let xs = g.rejected(&r).unwrap();
+                assert!(!n.accepts(&xs), "{} on {:?}", pattern, xs);
+            }
+        }
+        assert_eq!(g.rejected(&Regex::parse(".*").unwrap()), None);
+        assert!(g.rejected(&Regex::Nothing).is_some());
+    }
+
+    #[test]
+    fn test_generate_reuses_dfas() {
+        // the complement has a DFA of dozens of states, which was built
+        // again for every iteration of the star and every mutation tried
+        let r = Regex::parse("(?:~(?:(?:a|b)*a(?:a|b){5})b)*").unwrap();
+        let n = NFA::from_regex(&r);
+        let mut g = Generator::new(Rng::new(3), 1);
+        g.set_max_repeat(20);
+        for _ in 0..5 {
+            let xs = g.matching(&r).unwrap();
+            assert!(n.accepts(&xs), "{:?}", xs);
+            if let Some(xs) = g.rejected(&r) {
+                assert!(!n.accepts(&xs), "{:?}", xs);
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_random_regexes() {
+        let mut rng = Rng::new(2025);
+        let mut g = Generator::new(Rng::new(2026), 3);
+        for _ in 0..200 {
+            let r = random_regex(&mut rng, 5);
+            let n = NFA::from_regex(&r);
+            assert_eq!(g.matching(&r).is_none(), n.accepts_nothing(), "{:?}", r);
+            for _ in 0..10 {
+                if let Some(xs) = g.matching(&r) {
+                    assert!(n.accepts(&xs), "{:?} on {:?}", r, xs);
+                }
+                if let Some(xs) = g.rejected(&r) {
+                    assert!(!n.accepts(&xs), "{:?} on {:?}", r, xs);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use std::char;
+use std::collections::VecDeque;
+
+use super::{NFA, Regex};
+use super::sparse::SparseSet;
+
+/// Questions about the set of strings an NFA accepts, answered by searching
+/// its transition graph. Strings returned are accepted by `NFA::accepts`.
+impl NFA {
+
+    /// Whether no string at all is accepted
+    pub fn accepts_nothing(&self) -> bool {
+        !self.useful(&self.predecessors()).iter().any(|&u| u)
+    }
+
+    /// Whether only finitely many strings are accepted. This is so unless
+    /// some state on a path to acceptance lies on a cycle reading a
+    /// character; cycles of e-steps alone do not count.
+    pub fn is_finite(&self) -> bool {
+        let predecessors = self.predecessors();
+        let component = self.components(&self.useful(&predecessors), &predecessors);
+        self.nodes.iter().enumerate().all(|(s, node)| {
+            node.transitions.iter().all(|&(label, t)| {
+                label.is_none() || component[s].is_none() || component[s] != component[t]
+            })
+        })
+    }
+
+    /// A shortest accepted string, found by breadth first search in which
+    /// e-steps read nothing and so go to the front of the queue
+    pub fn shortest_accepted(&self) -> Option<Vec<char>> {
+        // length[s] is the fewest characters read on reaching s so far, and
+        // parent[s] the state and character it was reached from
+        let mut length: Vec<Option<usize>> = vec![None; self.nodes.len()];
+        let mut parent: Vec<Option<(usize, Option<char>)>> = vec![None; self.nodes.len()];
+        let mut queue = VecDeque::new();
+        length[self.start_idx] = Some(0);
+        queue.push_back(self.start_idx);
+
+        while let Some(s) = queue.pop_front() {
+            if self.is_accepting(s) {
+                let mut xs = vec![];
+                let mut t = s;
+                while let Some((p, c)) = parent[t] {
+                    xs.extend(c);
+                    t = p;
+                }
+                xs.reverse();
+                return Some(xs);
+            }
+            let here = length[s].unwrap();
+            for &(label, t) in self.nodes[s].transitions.iter() {
+                let there = if label.is_some() { here + 1 } else { here };
+                if length[t].is_none_or(|l| there < l) {
+                    length[t] = Some(there);
+                    parent[t] = Some((s, label.map(|(lo, _)| lo)));
+                    if label.is_some() {
+                        queue.push_back(t);
+                    } else {
+                        queue.push_front(t);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// The first `n` accepted strings in shortlex order, that is shorter
+    /// strings first and strings of the same length in lexicographic order.
+    /// Fewer are returned if fewer are accepted.
+    ///
+    /// Each length is searched depth first, following only characters after
+    /// which the remaining length can still be completed, so the search never
+    /// backtracks out of a prefix without finding a string.
+    pub fn first_accepted(&self, n: usize) -> Vec<Vec<char>> {
+        let reachable = search(self.nodes.len(), &[self.start_idx], |s| {
+            self.nodes[s].transitions.iter().map(|&(_, t)| t).collect()
+        });
+        let mut e_predecessors = vec![vec![]; self.nodes.len()];
+        for (s, node) in self.nodes.iter().enumerate() {
+            for &(label, t) in node.transitions.iter() {
+                if label.is_none() {
+                    e_predecessors[t].push(s);
+                }
+            }
+        }
+        // the reachable states with one of `seeds` in their epsilon closure
+        let closing_in = |seeds: &[usize]| -> Vec<bool> {
+            let found = search(self.nodes.len(), seeds, |s| e_predecessors[s].clone());
+            found.iter().zip(reachable.iter()).map(|(&f, &r)| f && r).collect()
+        };
+        // completes[k][s]: whether s is reachable and some path from it
+        // reading exactly k characters ends in an accepting state
+        let accepting: Vec<usize> = self.accepting.keys().cloned().collect();
+        let mut completes = vec![closing_in(&accepting)];
+        let mut start = SparseSet::new(self.nodes.len());
+        self.add_closure(&mut start, &mut vec![], self.start_idx);
+        let start: Vec<usize> = start.iter().cloned().collect();
+
+        let mut out = vec![];
+        let mut prefix = vec![];
+        // if no reachable state completes in k characters none completes in
+        // more, so every accepted string has been found
+        while out.len() < n && completes.last().unwrap().iter().any(|&c| c) {
+            let len = completes.len() - 1;
+            self.extend_accepted(&start, len, &completes, &mut prefix, &mut out, n);
+            let seeds: Vec<usize> = (0..self.nodes.len()).filter(|&u| {
+                self.nodes[u].transitions.iter().any(|&(label, t)| label.is_some() && completes[len][t])
+            }).collect();
+            let next = closing_in(&seeds);
+            completes.push(next);
+        }
+        out
+    }
+
+    /// Pushes onto `out`, in lexicographic order until it holds `n`, each
+    /// accepted string made of `prefix`, which leads to `states`, followed by
+    /// `remaining` more characters
+    fn extend_accepted(&self, states: &[usize], remaining: usize, completes: &[Vec<bool>],
+                       prefix: &mut Vec<char>, out: &mut Vec<Vec<char>>, n: usize) {
+        if remaining == 0 {
+            if states.iter().any(|&s| self.is_accepting(s)) {
+                out.push(prefix.clone());
+            }
+            return;
+        }
+        let live = &completes[remaining - 1];
+        let edges: Vec<(u32, u32, usize)> = states.iter()
+            .flat_map(|&s| self.nodes[s].transitions.iter())
+            .filter_map(|&(label, t)| match label {
+                Some((lo, hi)) if live[t] => Some((lo as u32, hi as u32, t)),
+                _ => None,
+            })
+            .collect();
+        // all the characters from one bound up to the next lead to the same states
+        let mut bounds: Vec<u32> = edges.iter().flat_map(|&(lo, hi, _)| vec![lo, hi + 1]).collect();
+        bounds.sort();
+        bounds.dedup();
+
+        let mut next = SparseSet::new(self.nodes.len());
+        let mut stack = vec![];
+        for w in bounds.windows(2) {
+            next.clear();
+            for &(lo, hi, t) in edges.iter() {
+                if lo <= w[0] && w[0] <= hi {
+                    self.add_closure(&mut next, &mut stack, t);
+                }
+            }
+            if next.is_empty() {
+                continue;
+            }
+            let targets: Vec<usize> = next.iter().cloned().collect();
+            for c in (w[0]..w[1]).filter_map(char::from_u32) {
+                prefix.push(c);
+                self.extend_accepted(&targets, remaining - 1, completes, prefix, out, n);
+                prefix.pop();
+                if out.len() == n {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// The states with a transition to each state
+    fn predecessors(&self) -> Vec<Vec<usize>> {
+        let mut predecessors = vec![vec![]; self.nodes.len()];
+        for (s, node) in self.nodes.iter().enumerate() {
+            for &(_, t) in node.transitions.iter() {
+                predecessors[t].push(s);
+            }
+        }
+        predecessors
+    }
+
+    /// Which states lie on some path from the start state to an accepting one
+    fn useful(&self, predecessors: &[Vec<usize>]) -> Vec<bool> {
+        let forward = search(self.nodes.len(), &[self.start_idx], |s| {
+            self.nodes[s].transitions.iter().map(|&(_, t)| t).collect()
+        });
+        let accepting: Vec<usize> = self.accepting.keys().cloned().collect();
+        let backward = search(self.nodes.len(), &accepting, |s| predecessors[s].clone());
+        forward.iter().zip(backward.iter()).map(|(&f, &b)| f && b).collect()
+    }
+
+    /// Numbers the strongly connected components of the graph restricted
+    /// to the useful states, by Kosaraju's algorithm. Other states get None.
+    fn components(&self, useful: &[bool], predecessors: &[Vec<usize>]) -> Vec<Option<usize>> {
+        // the useful states in the order a forward search finishes them
+        let mut order = vec![];
+        let mut seen = vec![false; self.nodes.len()];
+        for root in 0..self.nodes.len() {
+            if !useful[root] || seen[root] {
+                continue;
+            }
+            seen[root] = true;
+            let mut stack = vec![(root, 0)];
+            while let Some((s, i)) = stack.pop() {
+                match self.nodes[s].transitions.get(i) {
+                    Some(&(_, t)) => {
+                        stack.push((s, i + 1));
+                        if useful[t] && !seen[t] {
+                            seen[t] = true;
+                            stack.push((t, 0));
+                        }
+                    },
+                    None => order.push(s),
+                }
+            }
+        }
+
+        // searching backwards in reverse finishing order stays inside a component
+        let mut component = vec![None; self.nodes.len()];
+        let mut count = 0;
+        for &root in order.iter().rev() {
+            if component[root].is_some() {
+                continue;
+            }
+            component[root] = Some(count);
+            let mut stack = vec![root];
+            while let Some(s) = stack.pop() {
+                for &p in predecessors[s].iter() {
+                    if useful[p] && component[p].is_none() {
+                        component[p] = Some(count);
+                        stack.push(p);
+                    }
+                }
+            }
+            count += 1;
+        }
+        component
+    }
+}
+
+/// The same questions of a regex, through its Thompson NFA
+impl Regex {
+
+    /// Whether no string at all is matched
+    pub fn matches_nothing(&self) -> bool {
+        NFA::from_regex(self).accepts_nothing()
+    }
+
+    /// Whether only finitely many strings are matched
+    pub fn is_finite(&self) -> bool {
+        NFA::from_regex(self).is_finite()
+    }
+
+    /// A shortest matched string
+    pub fn shortest_match(&self) -> Option<Vec<char>> {
+        NFA::from_regex(self).shortest_accepted()
+    }
+
+    /// The first `n` matched strings in shortlex order
+    pub fn first_matches(&self, n: usize) -> Vec<Vec<char>> {
+        NFA::from_regex(self).first_accepted(n)
+    }
+}
+
+/// The states reachable from `roots` among `n`, where `next` gives the
+/// states one step on from each
+fn search<F>(n: usize, roots: &[usize], next: F) -> Vec<bool>
+    where F: Fn(usize) -> Vec<usize>
+{
+    let mut seen = vec![false; n];
+    let mut stack = vec![];
+    for &r in roots {
+        if !seen[r] {
+            seen[r] = true;
+            stack.push(r);
+        }
+    }
+    while let Some(s) = stack.pop() {
+        for t in next(s) {
+            if !seen[t] {
+                seen[t] = true;
+                stack.push(t);
+            }
+        }
+    }
+    seen
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::super::{DFA, NFA, Regex};
+    use super::super::test::strings;
+
+    fn parse(pattern: &str) -> Regex {
+        Regex::parse(pattern).unwrap()
+    }
+
+    fn first(pattern: &str, n: usize) -> Vec<String> {
+        parse(pattern).first_matches(n).iter().map(|xs| xs.iter().cloned().collect()).collect()
+    }
+
+    #[test]
+    fn test_matches_nothing() {
+        for pattern in &["[]", "a[]b", "a&b", "(?:a|b)[]*c&c*d", "~(?:.*)"] {
+            assert!(parse(pattern).matches_nothing(), "{}", pattern);
+            assert_eq!(parse(pattern).shortest_match(), None, "{}", pattern);
+        }
+        for pattern in &["", "[]*", "a|[]", "~a", "a*&(?:aa)+"] {
+            assert!(!parse(pattern).matches_nothing(), "{}", pattern);
+        }
+        assert!(NFA::nothing().accepts_nothing());
+    }
+
+    #[test]
+    fn test_is_finite() {
+        for pattern in &["", "a|bc", "(?:)*", "[]*", "(?:a*)[]", "(?:a|b){2,4}", "[a-z]&a*"] {
+            assert!(parse(pattern).is_finite(), "{}", pattern);
+        }
+        for pattern in &["a*", "(?:ab|c)+d", "(?:(?:)*a)*", "~a", "a*&(?:aa)+"] {
+            assert!(!parse(pattern).is_finite(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_shortest_match() {
+        for pattern in &["a*b", "(?:ab|c)+d", "x(?:y|z{3})|wwww", "(?:)|a", "[b-z]{2}&~(?:bc)"] {
+            let r = parse(pattern);
+            let n = NFA::from_regex(&r);
+            let xs = n.shortest_accepted().unwrap();
+            assert!(n.accepts(&xs), "{}", pattern);
+            assert_eq!(Some(xs.len()), DFA::from_nfa(&n).shortest_accepted().map(|ys| ys.len()), "{}", pattern);
+        }
+        assert_eq!(parse("x(?:y|z{3})|wwww").shortest_match(), Some(vec!['x', 'y']));
+    }
+
+    #[test]
+    fn test_first_matches() {
+        assert_eq!(first("a*b", 4), vec!["b", "ab", "aab", "aaab"]);
+        assert_eq!(first("[a-c]{1,2}", 5), vec!["a", "b", "c", "aa", "ab"]);
+        assert_eq!(first("a|bc|a|(?:b)c", 10), vec!["a", "bc"]);
+        assert_eq!(first("[c-d]*&~(?:.*cc.*)", 6), vec!["", "c", "d", "cd", "dc", "dd"]);
+        assert_eq!(first("(?:aaa)*", 3), vec!["", "aaa", "aaaaaa"]);
+        assert_eq!(first("[^a]", 2), vec!["\0", "\u{1}"]);
+        assert_eq!(first("[\u{d7ff}-\u{e000}]", 3), vec!["\u{d7ff}", "\u{e000}"]);
+        assert!(first("a*", 0).is_empty());
+        assert!(first("[]", 5).is_empty());
+    }
+
+    #[test]
+    fn test_first_matches_brute_force() {
+        let all = strings(&['a', 'b', 'c'], 4);
+        for pattern in &["(?:a|b)*c", "a(?:b|c)*|c*", "(?:ab|ba|c)+", "a?b?c?", "~(?:.*b.*)&[ac]*"] {
+            let n = NFA::from_regex(&parse(pattern));
+            let mut expected: Vec<Vec<char>> = all.iter().filter(|xs| n.accepts(xs)).cloned().collect();
+            expected.sort_by(|x, y| (x.len(), x).cmp(&(y.len(), y)));
+            assert_eq!(n.first_accepted(expected.len()), expected, "{}", pattern);
+        }
+    }
+}
@@ -0,0 +1,439 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use super::NFA;
+use super::alphabet::Alphabet;
+use super::sparse::SparseSet;
+
+/// Marks a transition that has not been computed yet
+const UNKNOWN: usize = usize::MAX;
+/// Marks a transition to the dead state
+const DEAD: usize = usize::MAX - 1;
+/// Begins the key of a state of `find` in which no match has been found,
+/// so a new match may still begin at its position
+const SEEDING: usize = usize::MAX;
+/// Begins the key of a state of `find` once a match has been found
+const SETTLED: usize = usize::MAX - 1;
+/// Ends each group in the key of a state of `find`
+const GROUP_END: usize = usize::MAX - 2;
+/// Stands for the group of a match beginning at the new position in the
+/// groups a transition of `find` keeps
+const NEW_GROUP: usize = usize::MAX;
+
+/// Matches by building DFA states from sets of NFA states on demand, so
+/// only the states a search actually visits are ever constructed. This
+/// avoids the exponential blowup the full subset construction suffers on
+/// patterns like `(a|b)*a(a|b){20}`.
+///
+/// Cached states are thrown away whenever they would exceed the memory
+/// limit. If that happens more than `max_flushes` times during a single
+/// search, the rest of the search falls back to simulating the NFA directly.
+pub struct LazyDFA<'a> {
+    nfa: &'a NFA,
+    alphabet: Alphabet,
+    memory_limit: usize,
+    max_flushes: usize,
+    /// The sorted set of NFA states for each cached DFA state, or for the
+    /// states of `find`, a marker followed by its groups
+    sets: Vec<Vec<usize>>,
+    ids: HashMap<Vec<usize>, usize>,
+    accepting: Vec<bool>,
+    /// The transition from state `s` on class `c` is at `s * alphabet.len() + c`
+    transitions: Vec<usize>,
+    /// For the transitions of `find`, the groups of the old state that the
+    /// groups of the new one continue, indexed like `transitions`
+    kept: Vec<Vec<usize>>,
+    memory_used: usize,
+    /// Totals over the lifetime of this matcher, for tuning the limits
+    flushes: usize,
+    fallbacks: usize,
+}
+
+impl<'a> LazyDFA<'a> {
+
+    /// A matcher whose cache holds at most around `memory_limit` bytes of
+    /// DFA states, falling back to the NFA after 8 flushes in one search
+    pub fn new(nfa: &'a NFA, memory_limit: usize) -> LazyDFA<'a> {
+        LazyDFA {
+            nfa: nfa,
+            alphabet: Alphabet::from_nfa(nfa),
+            memory_limit: memory_limit,
+            max_flushes: 8,
+            sets: vec![],
+            ids: HashMap::new(),
+            accepting: vec![],
+            transitions: vec![],
+            kept: vec![],
+            memory_used: 0,
+            flushes: 0,
+            fallbacks: 0,
+        }
+    }
+
+    pub fn set_max_flushes(&mut self, max_flushes: usize) {
+        self.max_flushes = max_flushes;
+    }
+
+    /// The number of DFA states currently cached
+    pub fn num_cached_states(&self) -> usize {
+        self.sets.len()
+    }
+
+    /// How many times the cache has been cleared
+    pub fn flushes(&self) -> usize {
+        self.flushes
+    }
+
+    /// How many searches have had to fall back to simulating the NFA
+    pub fn fallbacks(&self) -> usize {
+        self.fallbacks
+    }
+
+    pub fn accepts(&mut self, xs: &[char]) -> bool {
+        self.longest_prefix(xs.iter().cloned()) == Some(xs.len())
+    }
+
+    /// The leftmost-longest match in `haystack`, as a `(start, end)` byte span.
+    ///
+    /// This is a single left-to-right pass. As in `NFA::find_at`, a match may
+    /// begin at each position until one is found, so a state here is a list
+    /// of groups of NFA states, one for each position a match in progress
+    /// began at, earliest first. An NFA state is kept only in the earliest
+    /// group reaching it, and groups after the first accepting one are
+    /// dropped, as their matches would begin later. The positions are not
+    /// part of the state: each transition records which groups it keeps, and
+    /// the positions are carried alongside. If the cache is flushed too
+    /// often, the search is redone by simulating the NFA.
+    pub fn find(&mut self, haystack: &str) -> Option<(usize, usize)> {
+        let mut stack = vec![];
+        let mut initial = SparseSet::new(self.nfa.nodes.len());
+        self.nfa.add_closure(&mut initial, &mut stack, self.nfa.start_idx);
+        let mut key: Vec<usize> = initial.iter().cloned().collect();
+        key.sort();
+        let accepted = key.iter().any(|&s| self.nfa.is_accepting(s));
+        key.insert(0, if accepted { SETTLED } else { SEEDING });
+        key.push(GROUP_END);
+        let mut state = self.state_for_key(key);
+        let mut flushes = 0;
+
+        let mut starts = vec![0];
+        let mut next_starts = vec![];
+        let mut matched = if accepted { Some((0, 0)) } else { None };
+        for (i, c) in haystack.char_indices() {
+            let pos = i + c.len_utf8();
+            let class = self.alphabet.class_of(c);
+            let mut next = self.transitions[state * self.alphabet.len() + class];
+            if next == UNKNOWN {
+                let (key, kept) = self.step_groups(state, c);
+                if key.len() == 1 {
+                    next = DEAD;
+                } else {
+                    if self.is_full(key.len() + kept.len()) {
+                        if flushes == self.max_flushes {
+                            self.fallbacks += 1;
+                            return self.nfa.find(haystack);
+                        }
+                        // keep the current state so its transition can be filled in
+                        let current = self.sets[state].clone();
+                        self.flush();
+                        flushes += 1;
+                        state = self.state_for_key(current);
+                    }
+                    next = self.state_for_key(key);
+                }
+                self.memory_used += kept.len() * mem::size_of::<usize>();
+                self.transitions[state * self.alphabet.len() + class] = next;
+                self.kept[state * self.alphabet.len() + class] = kept;
+            }
+            if next == DEAD {
+                break;
+            }
+            next_starts.clear();
+            for &g in self.kept[state * self.alphabet.len() + class].iter() {
+                next_starts.push(if g == NEW_GROUP { pos } else { starts[g] });
+            }
+            mem::swap(&mut starts, &mut next_starts);
+            state = next;
+            if self.accepting[state] {
+                // only the last group can be accepting
+                matched = Some((starts[starts.len() - 1], pos));
+            }
+        }
+        matched
+    }
+
+    /// The key of the state `find` moves to from `state` on `c`, and which
+    /// groups of `state` its groups continue. A key with no groups, which
+    /// is just its marker, is the dead state.
+    fn step_groups(&self, state: usize, c: char) -> (Vec<usize>, Vec<usize>) {
+        let key = &self.sets[state];
+        let mut seen = SparseSet::new(self.nfa.nodes.len());
+        let mut stack = vec![];
+        let mut next = vec![SETTLED];
+        let mut kept = vec![];
+        let mut accepted = false;
+        // the key ends with GROUP_END, so the last slice is empty
+        for (g, group) in key[1..].split(|&s| s == GROUP_END).enumerate() {
+            let mark = seen.len();
+            for &s in group.iter() {
+                for &(label, t) in self.nfa.nodes[s].transitions.iter() {
+                    match label {
+                        Some((lo, hi)) if lo <= c && c <= hi => self.nfa.add_closure(&mut seen, &mut stack, t),
+                        _ => {}
+                    }
+                }
+            }
+            if seen.len() > mark {
+                accepted = self.push_group(&seen, mark, &mut next);
+                kept.push(g);
+                if accepted {
+                    break;
+                }
+            }
+        }
+        if key[0] == SEEDING && !accepted {
+            let mark = seen.len();
+            self.nfa.add_closure(&mut seen, &mut stack, self.nfa.start_idx);
+            if seen.len() > mark {
+                accepted = self.push_group(&seen, mark, &mut next);
+                kept.push(NEW_GROUP);
+            }
+            if !accepted {
+                next[0] = SEEDING;
+            }
+        }
+        (next, kept)
+    }
+
+    /// Appends to `key` the states added to `seen` after the first `mark`
+    /// as a group, returning whether any of them is accepting
+    fn push_group(&self, seen: &SparseSet, mark: usize, key: &mut Vec<usize>) -> bool {
+        let begin = key.len();
+        key.extend(seen.iter().skip(mark).cloned());
+        key[begin..].sort();
+        key.push(GROUP_END);
+        key[begin..].iter().any(|&s| self.nfa.is_accepting(s))
+    }
+
+    /// The length in characters of the longest prefix of `chars` accepted
+    fn longest_prefix<I: Iterator<Item=char>>(&mut self, chars: I) -> Option<usize> {
+        let mut start = HashSet::new();
+        start.insert(self.nfa.start_idx);
+        self.nfa.epsilon_closure(&mut start);
+        let mut state = self.state_for(start);
+        let mut flushes = 0;
+
+        let mut longest = if self.accepting[state] { Some(0) } else { None };
+        let mut chars = chars.enumerate();
+        while let Some((i, c)) = chars.next() {
+            let class = self.alphabet.class_of(c);
+            let mut next = self.transitions[state * self.alphabet.len() + class];
+            if next == UNKNOWN {
+                let mut set = self.nfa.step(&self.set_of(state), Some(c));
+                self.nfa.epsilon_closure(&mut set);
+                if set.is_empty() {
+                    next = DEAD;
+                } else {
+                    if self.is_full(set.len()) {
+                        if flushes == self.max_flushes {
+                            self.fallbacks += 1;
+                            return self.simulate(set, i + 1, chars, longest);
+                        }
+                        // keep the current state so its transition can be filled in
+                        let current = self.set_of(state);
+                        self.flush();
+                        flushes += 1;
+                        state = self.state_for(current);
+                    }
+                    next = self.state_for(set);
+                }
+                self.transitions[state * self.alphabet.len() + class] = next;
+            }
+            if next == DEAD {
+                break;
+            }
+            state = next;
+            if self.accepting[state] {
+                longest = Some(i + 1);
+            }
+        }
+        longest
+    }
+
+    /// Continues `longest_prefix` by direct NFA simulation, from the set
+    /// of states reached after `consumed` characters
+    fn simulate<I>(&self, mut states: HashSet<usize>, consumed: usize, chars: I, mut longest: Option<usize>)
+        -> Option<usize>
+        where I: Iterator<Item=(usize, char)>
+    {
+        if self.nfa.is_accepting_set(&states) {
+            longest = Some(consumed);
+        }
+        for (i, c) in chars {
+            states = self.nfa.step(&states, Some(c));
+            if states.is_empty() {
+                break;
+            }
+            self.nfa.epsilon_closure(&mut states);
+            if self.nfa.is_accepting_set(&states) {
+                longest = Some(i + 1);
+            }
+        }
+        longest
+    }
+
+    fn set_of(&self, state: usize) -> HashSet<usize> {
+        self.sets[state].iter().cloned().collect()
+    }
+
+    /// Returns the id of the cached state for `set`, creating it if necessary
+    fn state_for(&mut self, set: HashSet<usize>) -> usize {
+        let mut key = set.into_iter().collect::<Vec<usize>>();
+        key.sort();
+        self.state_for_key(key)
+    }
+
+    /// Returns the id of the cached state with key `key`, creating it if
+    /// necessary
+    fn state_for_key(&mut self, key: Vec<usize>) -> usize {
+        if let Some(&id) = self.ids.get(&key) {
+            return id;
+        }
+        let id = self.sets.len();
+        self.memory_used += self.state_size(key.len());
+        self.accepting.push(key.iter().any(|&s| self.nfa.is_accepting(s)));
+        self.transitions.extend(vec![UNKNOWN; self.alphabet.len()]);
+        self.kept.extend(vec![vec![]; self.alphabet.len()]);
+        self.ids.insert(key.clone(), id);
+        self.sets.push(key);
+        id
+    }
+
+    /// Approximate bytes used by a state with `set_len` NFA states: a row
+    /// of the transition table, plus its set stored in `sets` and in `ids`
+    fn state_size(&self, set_len: usize) -> usize {
+        let word = mem::size_of::<usize>();
+        self.alphabet.len() * word + 2 * set_len * word + 1
+    }
+
+    /// Whether adding a state with `set_len` NFA states would take the cache
+    /// over its limit
+    fn is_full(&self, set_len: usize) -> bool {
+        self.memory_used + self.state_size(set_len) > self.memory_limit
+    }
+
+    fn flush(&mut self) {
+        self.sets.clear();
+        self.ids.clear();
+        self.accepting.clear();
+        self.transitions.clear();
+        self.kept.clear();
+        self.memory_used = 0;
+        self.flushes += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::LazyDFA;
+    use super::super::{DFA, NFA, Regex};
+    use super::super::test::strings;
+
+    fn nfa(pattern: &str) -> NFA {
+        NFA::from_regex(&Regex::parse(pattern).unwrap())
+    }
+
+    #[test]
+    fn test_lazy_agrees_with_nfa() {
+        for pattern in &["(a|b)*abb", "[^b]+b?", "(ab|a)(bc|c)*", "", "[]"] {
+            let n = nfa(pattern);
+            let mut lazy = LazyDFA::new(&n, 1 << 20);
+            for s in strings(&['a', 'b', 'c'], 5) {
+                assert_eq!(n.accepts(&s), lazy.accepts(&s), "{} on {:?}", pattern, s);
+            }
+            assert_eq!(lazy.flushes(), 0);
+        }
+    }
+
+    #[test]
+    fn test_lazy_builds_only_visited_states() {
+        let n = nfa("(a|b)*a(a|b){20}");
+        let mut lazy = LazyDFA::new(&n, 1 << 20);
+        let mut input = vec!['b'; 30];
+        input[9] = 'a';
+
+        assert!(lazy.accepts(&input));
+        assert!(lazy.num_cached_states() <= input.len() + 1);
+        assert!(!lazy.accepts(&['a', 'b']));
+    }
+
+    #[test]
+    fn test_lazy_flushes_and_falls_back() {
+        let n = nfa("(a|b)*a(a|b){4}");
+        let inputs: Vec<Vec<char>> = strings(&['a', 'b'], 8).into_iter().filter(|s| s.len() > 5).collect();
+
+        // room for a handful of states only
+        let mut lazy = LazyDFA::new(&n, 2000);
+        for s in inputs.iter() {
+            assert_eq!(n.accepts(s), lazy.accepts(s), "{:?}", s);
+        }
+        assert!(lazy.flushes() > 0);
+
+        let mut lazy = LazyDFA::new(&n, 2000);
+        lazy.set_max_flushes(0);
+        for s in inputs.iter() {
+            assert_eq!(n.accepts(s), lazy.accepts(s), "{:?}", s);
+        }
+        assert!(lazy.fallbacks() > 0);
+        assert_eq!(lazy.flushes(), 0);
+    }
+
+    #[test]
+    fn test_lazy_find() {
+        let n = nfa("[0-9]+");
+        let mut lazy = LazyDFA::new(&n, 1 << 20);
+
+        assert_eq!(lazy.find("abc 123 45"), Some((4, 7)));
+        assert_eq!(lazy.find("ü9"), Some((2, 3)));
+        assert_eq!(lazy.find("abc"), None);
+        assert_eq!(nfa("b*").find("abc"), LazyDFA::new(&nfa("b*"), 1 << 20).find("abc"));
+    }
+
+    #[test]
+    fn test_lazy_find_agrees_with_nfa() {
+        for pattern in &["(a|b)*abb", "ab|b", "b*", "abcd|c", "a+b+|b", "(a|ab)(c|bcd)", "", "[]"] {
+            let n = nfa(pattern);
+            let mut lazy = LazyDFA::new(&n, 1 << 20);
+            // room for a few states only, so the cache is flushed and the
+            // search sometimes falls back to the NFA
+            let mut small = LazyDFA::new(&n, 300);
+            for s in strings(&['a', 'b', 'c', 'd'], 5) {
+                let haystack: String = s.into_iter().collect();
+                assert_eq!(n.find(&haystack), lazy.find(&haystack), "{} on {:?}", pattern, haystack);
+                assert_eq!(n.find(&haystack), small.find(&haystack), "{} on {:?}", pattern, haystack);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lazy_find_no_match_linear() {
+        // restarting at every position took quadratic time here
+        let haystack = "a".repeat(20000);
+        let n = nfa("[a-z]*0");
+        let mut lazy = LazyDFA::new(&n, 1 << 20);
+        assert_eq!(lazy.find(&haystack), None);
+        assert_eq!(lazy.find(&(haystack + "0")), Some((0, 20001)));
+        assert!(lazy.num_cached_states() <= 3);
+    }
+
+    #[test]
+    fn test_lazy_smaller_than_dfa() {
+        let n = nfa("(a|b)*a(a|b){10}");
+        let mut lazy = LazyDFA::new(&n, 1 << 24);
+        lazy.accepts(&['a'; 20]);
+
+        assert!(DFA::from_nfa(&n).num_states() > 1 << 10);
+        assert!(lazy.num_cached_states() <= 21);
+    }
+}
@@ -0,0 +1,178 @@
+use std::error::Error;
+use std::fmt;
+
+use super::{DFA, NFA, Regex};
+
+/// An ordered list of token rules, each a kind of token and the pattern for it
+pub struct LexerSpec<K> {
+    rules: Vec<(K, Regex)>,
+}
+
+impl<K: Clone> LexerSpec<K> {
+
+    pub fn new(rules: Vec<(K, Regex)>) -> LexerSpec<K> {
+        LexerSpec { rules: rules }
+    }
+
+    /// Combines the NFAs for all rules, each tagged with its position in the
+    /// list, and compiles them to a single minimal DFA
+    pub fn build(&self) -> Lexer<K> {
+        let subs: Vec<NFA> = self.rules
+            .iter()
+            .enumerate()
+            .map(|(i, r)| NFA::from_regex(&r.1).tagged(i))
+            .collect();
+        let (dfa, _) = DFA::from_nfa(&NFA::union(&subs)).minimize();
+
+        Lexer {
+            kinds: self.rules.iter().map(|r| r.0.clone()).collect(),
+            dfa: dfa,
+        }
+    }
+}
+
+/// A token of the given kind spanning the bytes `start..end` of the input
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Token<K> {
+    pub kind: K,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// No rule matches a non-empty prefix of the input at byte offset `offset`
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct LexError {
+    pub offset: usize,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "no token matches at offset {}", self.offset)
+    }
+}
+
+impl Error for LexError {}
+
+/// Splits input into tokens by maximal munch: at each position the longest
+/// match of any rule is taken, with ties going to the earliest rule
+pub struct Lexer<K> {
+    kinds: Vec<K>,
+    /// Accepting states are tagged with the earliest rule they accept
+    dfa: DFA,
+}
+
+impl<K: Clone> Lexer<K> {
+
+    pub fn tokenize(&self, input: &str) -> Result<Vec<Token<K>>, LexError> {
+        let mut tokens = vec![];
+        let mut pos = 0;
+        while pos < input.len() {
+            match self.longest_match_at(input, pos) {
+                Some((end, rule)) if end > pos => {
+                    tokens.push(Token { kind: self.kinds[rule].clone(), start: pos, end: end });
+                    pos = end;
+                },
+                _ => return Err(LexError { offset: pos }),
+            }
+        }
+        Ok(tokens)
+    }
+
+    /// The end of the longest match starting at `start`, along with the
+    /// earliest rule matching that much
+    fn longest_match_at(&self, input: &str, start: usize) -> Option<(usize, usize)> {
+        let mut state = self.dfa.start();
+        let mut best = self.dfa.rule(state).map(|rule| (start, rule));
+        for (i, c) in input[start..].char_indices() {
+            state = match self.dfa.next(state, c) {
+                Some(s) => s,
+                None => break,
+            };
+            if let Some(rule) = self.dfa.rule(state) {
+                best = Some((start + i + c.len_utf8(), rule));
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::{LexError, LexerSpec, Token};
+    use super::super::Regex;
+
+    #[derive(Debug,Clone,Copy,PartialEq,Eq)]
+    enum Kind { If, Ident, Int, Space, Arrow, Minus }
+
+    fn spec() -> LexerSpec<Kind> {
+        let rules = vec![
+            (Kind::If, "if"),
+            (Kind::Ident, "[a-z][a-z0-9]*"),
+            (Kind::Int, "[0-9]+"),
+            (Kind::Space, "[ \\n]+"),
+            (Kind::Arrow, "->"),
+            (Kind::Minus, "-"),
+        ];
+        LexerSpec::new(rules.into_iter().map(|(k, p)| (k, Regex::parse(p).unwrap())).collect())
+    }
+
+    fn kinds(input: &str) -> Vec<Kind> {
+        spec().build().tokenize(input).unwrap().into_iter().map(|t| t.kind).collect()
+    }
+
+    #[test]
+    fn test_lexer_spans() {
+        let tokens = spec().build().tokenize("if x1 -> 42").unwrap();
+
+        assert_eq!(tokens, vec![
+            Token { kind: Kind::If, start: 0, end: 2 },
+            Token { kind: Kind::Space, start: 2, end: 3 },
+            Token { kind: Kind::Ident, start: 3, end: 5 },
+            Token { kind: Kind::Space, start: 5, end: 6 },
+            Token { kind: Kind::Arrow, start: 6, end: 8 },
+            Token { kind: Kind::Space, start: 8, end: 9 },
+            Token { kind: Kind::Int, start: 9, end: 11 },
+        ]);
+    }
+
+    #[test]
+    fn test_lexer_maximal_munch() {
+        // the longest match wins, even over an earlier rule
+        assert_eq!(kinds("iffy"), vec![Kind::Ident]);
+        assert_eq!(kinds("if"), vec![Kind::If]);
+        assert_eq!(kinds("->-"), vec![Kind::Arrow, Kind::Minus]);
+        assert_eq!(kinds("12ab"), vec![Kind::Int, Kind::Ident]);
+        assert_eq!(kinds(""), vec![]);
+    }
+
+    #[test]
+    fn test_lexer_errors() {
+        let lexer = spec().build();
+
+        assert_eq!(lexer.tokenize("x @"), Err(LexError { offset: 2 }));
+        assert_eq!(lexer.tokenize("X"), Err(LexError { offset: 0 }));
+
+        // rules that only match the empty string make no progress
+        let lexer = LexerSpec::new(vec![(0, Regex::parse("a*").unwrap())]).build();
+        assert_eq!(lexer.tokenize("aab"), Err(LexError { offset: 2 }));
+    }
+
+    #[test]
+    fn test_lexer_intersection_complement() {
+        // identifiers exclude keywords, whichever rule comes first
+        let rules = vec![
+            ("ident", "[a-z]+&~(?:if|else)"),
+            ("keyword", "if|else"),
+            ("comment", "\\(\\*~(?:.*\\*\\).*)\\*\\)"),
+            ("space", " +"),
+        ];
+        let lexer = LexerSpec::new(rules.into_iter().map(|(k, p)| (k, Regex::parse(p).unwrap())).collect()).build();
+        let kinds = |input: &str| lexer.tokenize(input).map(|ts| ts.into_iter().map(|t| t.kind).collect::<Vec<_>>());
+
+        assert_eq!(kinds("if iffy else"), Ok(vec!["keyword", "space", "ident", "space", "keyword"]));
+        assert_eq!(kinds("(* a * ) *) b"), Ok(vec!["comment", "space", "ident"]));
+        // a comment ends at the first `*)`
+        assert_eq!(kinds("(* a *) *)"), Err(LexError { offset: 8 }));
+    }
+}
@@ -1,47 +1,256 @@
 
-use std::collections::HashSet;
-
-#[derive(Debug,Clone)]
+// `field: field` initializers are the house style, and an alphabet always
+// has at least one class, so its `len` has no `is_empty` to go with it
+#![allow(clippy::redundant_field_names, clippy::len_without_is_empty)]
+
+use std::collections::{BTreeMap, HashSet};
+use std::mem;
+
+mod alphabet;
+mod biguint;
+#[cfg(test)]
+mod bench;
+mod canonical;
+mod capture;
+mod class;
+mod count;
+mod derivative;
+mod display;
+mod dfa;
+mod dot;
+mod equivalence;
+mod generate;
+mod language;
+mod lazy;
+mod lexer;
+mod parse;
+mod rng;
+mod search;
+mod simplify;
+mod sparse;
+
+pub use alphabet::Alphabet;
+pub use biguint::BigUint;
+pub use capture::Captures;
+pub use class::CharClass;
+pub use dfa::DFA;
+pub use generate::Generator;
+pub use lazy::LazyDFA;
+pub use lexer::{LexError, Lexer, LexerSpec, Token};
+pub use parse::{ParseError, ParseErrorKind};
+pub use rng::Rng;
+pub use search::{FindIter, MatchKind};
+use sparse::SparseSet;
+
+/// Equality, hashing and ordering are structural, so regexes for the same
+/// language may differ unless both are put in `canonical` form first.
+///
+/// Most operations, including cloning, comparison, `Display`, `simplify`
+/// and `canonical`, recurse once per level of nesting, so they may overflow
+/// the stack on regexes more than a few thousand deep. `parse` rejects
+/// patterns deeper than 2500, but the builders do not check. Only dropping
+/// and `NFA::from_regex` work at any depth.
+#[derive(Debug,Clone,PartialEq,Eq,Hash,PartialOrd,Ord)]
 pub enum Regex {
+    /// The empty language, matching no strings at all
+    Nothing,
+    /// Epsilon, matching only the empty string
     Empty,
     Single(char),
+    Class(CharClass),
     Or(Box<Regex>, Box<Regex>),
     Then(Box<Regex>, Box<Regex>),
     Star(Box<Regex>),
+    /// Records the span matched by the inner regex as the numbered group.
+    /// Group 0 is reserved for the whole match.
+    Capture(usize, Box<Regex>),
+    /// Intersection, matching the strings both operands match
+    And(Box<Regex>, Box<Regex>),
+    /// Complement, matching exactly the strings the operand does not
+    Not(Box<Regex>),
+}
+
+/// Drops the operands of each regex from a stack of its own, as the derived
+/// recursive drop would overflow the call stack on deeply nested regexes.
+/// Because of this, operands cannot be moved out of a regex by pattern
+/// matching; take them with `mem::replace` instead.
+impl Drop for Regex {
+    fn drop(&mut self) {
+        let mut operands = vec![];
+        self.take_operands(&mut operands);
+        while let Some(mut r) = operands.pop() {
+            r.take_operands(&mut operands);
+        }
+    }
 }
 
+/// The builders taking `&self` clone their operands, which is convenient for
+/// small regexes but quadratic when growing one step by step. The `into_`
+/// builders and `alternation` and `concatenation` take ownership instead.
 impl Regex {
 
     pub fn or(&self, s: &Regex) -> Regex {
-        Regex::Or(Box::new(self.clone()), Box::new(s.clone()))
+        self.clone().into_or(s.clone())
     }
 
     pub fn then(&self, s: &Regex) -> Regex {
-        Regex::Then(Box::new(self.clone()), Box::new(s.clone()))
+        self.clone().into_then(s.clone())
     }
 
     pub fn star(&self) -> Regex {
-        Regex::Star(Box::new(self.clone()))
+        self.clone().into_star()
+    }
+
+    pub fn and(&self, s: &Regex) -> Regex {
+        self.clone().into_and(s.clone())
+    }
+
+    pub fn not(&self) -> Regex {
+        self.clone().into_not()
+    }
+
+    pub fn into_or(self, s: Regex) -> Regex {
+        Regex::Or(Box::new(self), Box::new(s))
+    }
+
+    pub fn into_then(self, s: Regex) -> Regex {
+        Regex::Then(Box::new(self), Box::new(s))
+    }
+
+    pub fn into_star(self) -> Regex {
+        Regex::Star(Box::new(self))
+    }
+
+    pub fn into_and(self, s: Regex) -> Regex {
+        Regex::And(Box::new(self), Box::new(s))
+    }
+
+    pub fn into_not(self) -> Regex {
+        Regex::Not(Box::new(self))
+    }
+
+    /// The alternatives chained to the left, as `parse` would, or `Nothing`
+    /// if there are none
+    pub fn alternation<I: IntoIterator<Item=Regex>>(alternatives: I) -> Regex {
+        let mut alternatives = alternatives.into_iter();
+        match alternatives.next() {
+            None => Regex::Nothing,
+            Some(first) => alternatives.fold(first, Regex::into_or),
+        }
+    }
+
+    /// The parts chained to the left, as `parse` would, or `Empty` if there
+    /// are none
+    pub fn concatenation<I: IntoIterator<Item=Regex>>(parts: I) -> Regex {
+        let mut parts = parts.into_iter();
+        match parts.next() {
+            None => Regex::Empty,
+            Some(first) => parts.fold(first, Regex::into_then),
+        }
+    }
+
+    /// Moves any operands that have operands of their own onto `operands`,
+    /// leaving `Empty` in their place
+    fn take_operands(&mut self, operands: &mut Vec<Regex>) {
+        let mut take = |r: &mut Regex| match *r {
+            Regex::Nothing | Regex::Empty | Regex::Single(_) | Regex::Class(_) => {},
+            _ => operands.push(mem::replace(r, Regex::Empty)),
+        };
+        match *self {
+            Regex::Or(ref mut r, ref mut s) | Regex::Then(ref mut r, ref mut s) | Regex::And(ref mut r, ref mut s) => {
+                take(r);
+                take(s);
+            },
+            Regex::Star(ref mut r) | Regex::Capture(_, ref mut r) | Regex::Not(ref mut r) => take(r),
+            _ => {}
+        }
+    }
+
+    /// Panics if `group` is 0, as that group is always the whole match
+    pub fn capture(&self, group: usize) -> Regex {
+        assert!(group > 0, "group 0 is reserved for the whole match");
+        Regex::Capture(group, Box::new(self.clone()))
+    }
+
+    pub fn class(ranges: &[(char, char)], negated: bool) -> Regex {
+        Regex::Class(CharClass::new(ranges, negated))
+    }
+
+    /// Matches any single character
+    pub fn any() -> Regex {
+        Regex::class(&[], true)
+    }
+
+    /// One or more repetitions, desugared to `r r*`
+    pub fn plus(&self) -> Regex {
+        self.then(&self.star())
+    }
+
+    /// Zero or one repetitions, desugared to `r|ε`
+    pub fn optional(&self) -> Regex {
+        self.or(&Regex::Empty)
+    }
+
+    /// Exactly `n` repetitions, `r{n}`
+    pub fn repeat(&self, n: usize) -> Regex {
+        Regex::concatenation((0..n).map(|_| self.clone()))
+    }
+
+    /// At least `n` repetitions, `r{n,}`
+    pub fn repeat_at_least(&self, n: usize) -> Regex {
+        if n == 0 {
+            self.star()
+        } else {
+            self.repeat(n).into_then(self.star())
+        }
+    }
+
+    /// Between `n` and `m` repetitions inclusive, `r{n,m}`. The optional
+    /// copies are nested as `(r(r)?)?` rather than chained as `r?r?` so
+    /// that there is only one way to match each count.
+    ///
+    /// Panics if `m < n`.
+    pub fn repeat_between(&self, n: usize, m: usize) -> Regex {
+        assert!(n <= m, "invalid repetition bounds {{{},{}}}", n, m);
+        let mut tail: Option<Regex> = None;
+        for _ in n..m {
+            tail = Some(match tail {
+                None => self.optional(),
+                Some(t) => self.clone().into_then(t).into_or(Regex::Empty),
+            });
+        }
+        match tail {
+            None => self.repeat(n),
+            Some(t) => if n == 0 { t } else { self.repeat(n).into_then(t) },
+        }
     }
 }
 
 #[derive(Debug,Clone)]
 struct Node {
-    /// Transitions with first entry None are e-steps
-    transitions: Vec<(Option<char>, usize)>,
+    /// Transitions with first entry None are e-steps, all others
+    /// are labelled with an inclusive range of characters
+    transitions: Vec<(Option<(char, char)>, usize)>,
+    /// A capture slot to record the current position in on entering this
+    /// node. Group `g` starts at slot `2g` and ends at slot `2g + 1`.
+    save: Option<usize>,
 }
 
 impl Node {
     fn neighbours(&self, a: Option<char>) -> Vec<usize> {
         self.transitions
             .iter()
-            .filter(|t| t.0 == a)
+            .filter(|t| match (t.0, a) {
+                (None, None) => true,
+                (Some((lo, hi)), Some(c)) => lo <= c && c <= hi,
+                _ => false,
+            })
             .map(|x| x.1)
             .collect::<Vec<usize>>()
     }
 
-    fn new(ts: Vec<(Option<char>, usize)>) -> Node {
-        Node { transitions: ts }
+    fn new(ts: Vec<(Option<(char, char)>, usize)>) -> Node {
+        Node { transitions: ts, save: None }
     }
 }
 
@@ -49,101 +258,205 @@ impl Node {
 pub struct NFA {
     nodes: Vec<Node>,
     start_idx: usize,
-    final_idx: usize,
+    /// Accepting states, each tagged with the rule it accepts for, if any.
+    /// The Thompson constructions always produce a single untagged one.
+    accepting: BTreeMap<usize, Option<usize>>,
 }
 
 impl NFA {
 
-    pub fn empty() -> NFA {
+    /// An NFA with a single untagged accepting state
+    fn with_final(nodes: Vec<Node>, start_idx: usize, final_idx: usize) -> NFA {
+        let mut accepting = BTreeMap::new();
+        accepting.insert(final_idx, None);
         NFA {
-            nodes: vec![Node::new(vec![(None, 1)]), Node::new(vec![])],
-            start_idx: 0,
-            final_idx: 1,
+            nodes: nodes,
+            start_idx: start_idx,
+            accepting: accepting,
         }
     }
 
-    pub fn single(a: char) -> NFA {
-        NFA {
-            nodes: vec![Node::new(vec![(Some(a), 1)]), Node::new(vec![])],
-            start_idx: 0,
-            final_idx: 1,
-        }
+    /// Accepts nothing, as the final state is unreachable
+    pub fn nothing() -> NFA {
+        Self::with_final(vec![Node::new(vec![]), Node::new(vec![])], 0, 1)
     }
 
-    pub fn from_regex(reg: &Regex) -> NFA {
-        return match *reg {
-            Regex::Empty => Self::empty(),
-            Regex::Single(c) => Self::single(c),
-            Regex::Or(ref r, ref s) => {
-                let nr = Self::from_regex(&*r);
-                let ns = Self::from_regex(&*s);
-                Self::or(nr, ns)
-            },
-            Regex::Then(ref r, ref s) => {
-                let nr = Self::from_regex(&*r);
-                let ns = Self::from_regex(&*s);
-                Self::then(nr, ns)
-            },
-            Regex::Star(ref r) => Self::star(Self::from_regex(&*r))
-        };
+    /// Accepts only the empty string
+    pub fn empty() -> NFA {
+        Self::with_final(vec![Node::new(vec![(None, 1)]), Node::new(vec![])], 0, 1)
     }
 
-    fn then(a: NFA, b: NFA) -> NFA {
-        let mut nodes = vec![Node::new(vec![]); a.nodes.len() + b.nodes.len() + 2];
-        let start_idx = 0;
-        let final_idx = nodes.len() - 1;
-        nodes[start_idx] = Node::new(vec![
-           (None, 1), // e-step to start of embedded copy of a
-        ]);
-        nodes[final_idx] = Node::new(vec![]);
+    pub fn single(a: char) -> NFA {
+        Self::with_final(vec![Node::new(vec![(Some((a, a)), 1)]), Node::new(vec![])], 0, 1)
+    }
 
-        Self::embed(&mut nodes, &a, 1, &[a.nodes.len() + 1]);
-        Self::embed(&mut nodes, &b, 1 + a.nodes.len(), &[final_idx]);
+    /// A single transition per range in the class, rather than one per character
+    pub fn class(c: &CharClass) -> NFA {
+        let ts = c.ranges().into_iter().map(|r| (Some(r), 1)).collect();
+        Self::with_final(vec![Node::new(ts), Node::new(vec![])], 0, 1)
+    }
 
+    /// One node per DFA state, with a transition for each class that doesn't
+    /// lead to the dead state. Accepting states keep their rule tags.
+    pub fn from_dfa(dfa: &DFA) -> NFA {
+        let alphabet = dfa.alphabet();
+        let mut nodes = vec![];
+        let mut accepting = BTreeMap::new();
+        for s in 0..dfa.num_states() {
+            let mut ts = vec![];
+            for c in 0..alphabet.len() {
+                if let Some(t) = dfa.next_class(s, c) {
+                    ts.push((Some(alphabet.range(c)), t));
+                }
+            }
+            nodes.push(Node::new(ts));
+            if dfa.is_accepting(s) {
+                accepting.insert(s, dfa.rule(s));
+            }
+        }
         NFA {
             nodes: nodes,
-            start_idx: start_idx,
-            final_idx: final_idx,
+            start_idx: dfa.start(),
+            accepting: accepting,
         }
     }
 
-    fn or(a: NFA, b: NFA) -> NFA {
-        let mut nodes = vec![Node::new(vec![]); a.nodes.len() + b.nodes.len() + 2];
-        let start_idx = 0;
-        let final_idx = nodes.len() - 1;
-        nodes[start_idx] = Node::new(vec![
-            (None, 1),                // e-step to start of embedded copy of a
-            (None, 1 + a.nodes.len()) // e-step to start of embedded copy of b
-        ]);
-        nodes[final_idx] = Node::new(vec![]);
-
-        Self::embed(&mut nodes, &a, 1, &[final_idx]);
-        Self::embed(&mut nodes, &b, 1 + a.nodes.len(), &[final_idx]);
+    /// Tags every accepting state with `rule`
+    pub fn tagged(mut self, rule: usize) -> NFA {
+        for tag in self.accepting.values_mut() {
+            *tag = Some(rule);
+        }
+        self
+    }
 
+    /// Embeds each NFA under a fresh start state, keeping all of their
+    /// accepting states and tags rather than joining them in a new final state
+    pub fn union(subs: &[NFA]) -> NFA {
+        let size = subs.iter().map(|n| n.nodes.len()).sum::<usize>() + 1;
+        let mut nodes = vec![Node::new(vec![]); size];
+        let mut accepting = BTreeMap::new();
+        let mut offset = 1;
+        for sub in subs {
+            nodes[0].transitions.push((None, offset + sub.start_idx));
+            Self::embed(&mut nodes, sub, offset, &[]);
+            for (&s, &tag) in sub.accepting.iter() {
+                accepting.insert(offset + s, tag);
+            }
+            offset += sub.nodes.len();
+        }
         NFA {
             nodes: nodes,
-            start_idx: start_idx,
-            final_idx: final_idx,
+            start_idx: 0,
+            accepting: accepting,
         }
     }
 
-    fn star(a: NFA) -> NFA {
-        let mut nodes = vec![Node::new(vec![]); a.nodes.len() + 2];
-        let start_idx = 0;
-        let final_idx = nodes.len() - 1;
-        nodes[start_idx] = Node::new(vec![
-            (None, 1),          // e-step to start of embedded copy of a
-            (None, final_idx)   // e-step to accepting state
-        ]);
-        nodes[final_idx] = Node::new(vec![]);
-    
-        Self::embed(&mut nodes, &a, 1, &[final_idx, 0]);
-    
-        NFA {
-            nodes: nodes,
-            start_idx: start_idx,
-            final_idx: final_idx,
+    /// The Thompson construction, appending every fragment to one node
+    /// arena. Operands are compiled before the operators that use them,
+    /// with an explicit stack in place of recursion, so that deeply nested
+    /// regexes compile in linear time without overflowing the call stack.
+    ///
+    /// Intersections and complements have no Thompson fragment, so each is
+    /// compiled separately through a DFA with `intersection` or `complement`
+    /// and embedded whole. Groups inside them are never reported.
+    pub fn from_regex(reg: &Regex) -> NFA {
+        let mut t = Thompson { nodes: vec![] };
+        // the fragments for the operands compiled so far, the rightmost last
+        let mut fragments: Vec<Fragment> = vec![];
+        // each regex is visited twice, to push its operands and then to combine them
+        let mut stack = vec![(reg, false)];
+        while let Some((r, combine)) = stack.pop() {
+            if !combine {
+                stack.push((r, true));
+                match *r {
+                    Regex::Or(ref a, ref b) | Regex::Then(ref a, ref b) => {
+                        stack.push((b, false));
+                        stack.push((a, false));
+                    },
+                    Regex::Star(ref a) | Regex::Capture(_, ref a) => stack.push((a, false)),
+                    _ => {}
+                }
+                continue;
+            }
+            let f = match *r {
+                Regex::Nothing => t.leaf(vec![]),
+                Regex::Empty => t.leaf(vec![None]),
+                Regex::Single(c) => t.leaf(vec![Some((c, c))]),
+                Regex::Class(ref k) => t.leaf(k.ranges().into_iter().map(Some).collect()),
+                Regex::Or(_, _) | Regex::Then(_, _) => {
+                    let b = fragments.pop().unwrap();
+                    let a = fragments.pop().unwrap();
+                    if let Regex::Or(_, _) = *r { t.or(a, b) } else { t.then(a, b) }
+                },
+                Regex::Star(_) => {
+                    let a = fragments.pop().unwrap();
+                    t.star(a)
+                },
+                Regex::Capture(g, _) => {
+                    let a = fragments.pop().unwrap();
+                    t.capture(a, g)
+                },
+                Regex::And(ref a, ref b) => t.embed(&Self::intersection(&Self::from_regex(a), &Self::from_regex(b))),
+                Regex::Not(ref a) => t.embed(&Self::complement(&Self::from_regex(a))),
+            };
+            fragments.push(f);
         }
+        let f = fragments.pop().unwrap();
+        t.finish(f)
+    }
+
+    /// Accepts a string accepted by `a` followed by one accepted by `b`.
+    /// Like `or` and `star`, the result has a single untagged accepting state.
+    pub fn then(a: NFA, b: NFA) -> NFA {
+        let mut t = Thompson { nodes: vec![] };
+        let (fa, fb) = (t.embed(&a), t.embed(&b));
+        let f = t.then(fa, fb);
+        t.finish(f)
+    }
+
+    pub fn or(a: NFA, b: NFA) -> NFA {
+        let mut t = Thompson { nodes: vec![] };
+        let (fa, fb) = (t.embed(&a), t.embed(&b));
+        let f = t.or(fa, fb);
+        t.finish(f)
+    }
+
+    pub fn star(a: NFA) -> NFA {
+        let mut t = Thompson { nodes: vec![] };
+        let fa = t.embed(&a);
+        let f = t.star(fa);
+        t.finish(f)
+    }
+
+    /// Accepts the strings both `a` and `b` accept, through the product of
+    /// their DFAs, minimized
+    pub fn intersection(a: &NFA, b: &NFA) -> NFA {
+        let alphabet = Alphabet::from_nfas(&[a, b]);
+        let da = DFA::from_nfa_with_alphabet(a, alphabet.clone());
+        let db = DFA::from_nfa_with_alphabet(b, alphabet);
+        NFA::from_dfa(&da.intersect(&db).minimize().0)
+    }
+
+    /// Accepts exactly the strings `a` does not, through its DFA, minimized
+    pub fn complement(a: &NFA) -> NFA {
+        NFA::from_dfa(&DFA::from_nfa(a).complement().minimize().0)
+    }
+
+    /// Accepts the reverse of each string this accepts, by flipping every
+    /// transition. A new start state has e-steps to each accepting state,
+    /// and the old start state is the only accepting one, untagged. Capture
+    /// slots are dropped.
+    pub fn reverse(&self) -> NFA {
+        // the new start state goes last, so the old indices stay the same
+        let start_idx = self.nodes.len();
+        let mut nodes = vec![Node::new(vec![]); start_idx + 1];
+        for (s, node) in self.nodes.iter().enumerate() {
+            for &(label, t) in node.transitions.iter() {
+                nodes[t].transitions.push((label, s));
+            }
+        }
+        nodes[start_idx].transitions = self.accepting.keys().map(|&s| (None, s)).collect();
+        Self::with_final(nodes, start_idx, self.start_idx)
     }
 
     fn embed(nodes: &mut [Node], sub: &NFA, offset: usize, final_trans: &[usize]) {
@@ -153,8 +466,8 @@ impl NFA {
                 // update internal pointers
                 p.1 += offset;
             }
-            if i == sub.final_idx {
-                // e-steps from end of embedded NFA
+            if sub.accepting.contains_key(&i) {
+                // e-steps from ends of embedded NFA
                 for &t in final_trans {
                     m.transitions.push((None, t));    
                 }
@@ -164,35 +477,80 @@ impl NFA {
     }
 
     pub fn accepts(&self, xs: &[char]) -> bool {
-        let mut states = HashSet::new();
-        states.insert(self.start_idx);
-        self.epsilon_closure(&mut states);
+        self.simulate(xs).iter().any(|&s| self.is_accepting(s))
+    }
+
+    /// The earliest rule tagging an accepting state reached on `xs`
+    pub fn accepted_rule(&self, xs: &[char]) -> Option<usize> {
+        self.simulate(xs).iter().filter_map(|s| self.accepting.get(s).cloned().and_then(|t| t)).min()
+    }
+
+    /// The states reached on `xs`. Runs in O(n·m) for n characters and m
+    /// nodes and transitions, using two preallocated sets which are swapped
+    /// after each step and one preallocated stack for the closures.
+    fn simulate(&self, xs: &[char]) -> SparseSet {
+        let mut states = SparseSet::new(self.nodes.len());
+        let mut next = SparseSet::new(self.nodes.len());
+        let mut stack = Vec::with_capacity(self.nodes.len());
+        self.add_closure(&mut states, &mut stack, self.start_idx);
 
         for &c in xs.iter() {
-            states = self.step(&states, Some(c));
+            next.clear();
+            for &s in states.iter() {
+                for &(label, t) in self.nodes[s].transitions.iter() {
+                    match label {
+                        Some((lo, hi)) if lo <= c && c <= hi => self.add_closure(&mut next, &mut stack, t),
+                        _ => {}
+                    }
+                }
+            }
+            mem::swap(&mut states, &mut next);
             if states.is_empty() {
-                return false;
+                break;
             }
-            self.epsilon_closure(&mut states);
         }
+        states
+    }
 
-        states.contains(&self.final_idx)
+    /// Adds `s` and the states reachable from it by e-steps to `states`.
+    /// The search stops at states already in the set, so each state is
+    /// visited at most once however many closures are added between clears.
+    /// `stack` is scratch space, left empty on return.
+    fn add_closure(&self, states: &mut SparseSet, stack: &mut Vec<usize>, s: usize) {
+        if !states.insert(s) {
+            return;
+        }
+        stack.push(s);
+        while let Some(s) = stack.pop() {
+            for &(label, t) in self.nodes[s].transitions.iter() {
+                if label.is_none() && states.insert(t) {
+                    stack.push(t);
+                }
+            }
+        }
+    }
+
+    pub fn is_accepting(&self, s: usize) -> bool {
+        self.accepting.contains_key(&s)
+    }
+
+    fn is_accepting_set(&self, states: &HashSet<usize>) -> bool {
+        states.iter().any(|&s| self.is_accepting(s))
+    }
+
+    /// The earliest rule tagging any of `states`
+    fn rule_of_set(&self, states: &HashSet<usize>) -> Option<usize> {
+        states.iter().filter_map(|s| self.accepting.get(s).cloned().and_then(|t| t)).min()
     }
 
     fn epsilon_closure(&self, states: &mut HashSet<usize>) {
-        let mut size = states.len();
-        loop {
-            let mut new_nodes = self.step(states, None);
-            if new_nodes.len() == 0 {
-                break;
-            }
-            for n in new_nodes.into_iter() {
-                states.insert(n);
-            }
-            if states.len() == size {
-                break;
+        let mut worklist: Vec<usize> = states.iter().cloned().collect();
+        while let Some(s) = worklist.pop() {
+            for n in self.nodes[s].neighbours(None) {
+                if states.insert(n) {
+                    worklist.push(n);
+                }
             }
-            size = states.len();
         }
     }
 
@@ -207,6 +565,87 @@ impl NFA {
     }
 }
 
+/// The start and final node of part of an NFA under construction. The
+/// final node has no transitions until the fragment is used as an operand.
+type Fragment = (usize, usize);
+
+/// Builds Thompson NFAs by appending fragments to a single node arena, so
+/// that combining fragments only adds the nodes and e-steps joining them
+/// rather than copying their operands
+struct Thompson {
+    nodes: Vec<Node>,
+}
+
+impl Thompson {
+
+    fn node(&mut self, transitions: Vec<(Option<(char, char)>, usize)>) -> usize {
+        self.nodes.push(Node::new(transitions));
+        self.nodes.len() - 1
+    }
+
+    /// A start node with a transition to the final node for each label
+    fn leaf(&mut self, labels: Vec<Option<(char, char)>>) -> Fragment {
+        let final_idx = self.nodes.len() + 1;
+        let start_idx = self.node(labels.into_iter().map(|l| (l, final_idx)).collect());
+        (start_idx, self.node(vec![]))
+    }
+
+    /// Copies all of `nfa`, with e-steps from its accepting states to a new
+    /// final node
+    fn embed(&mut self, nfa: &NFA) -> Fragment {
+        let offset = self.nodes.len();
+        let final_idx = offset + nfa.nodes.len();
+        for (i, n) in nfa.nodes.iter().enumerate() {
+            let mut m = n.clone();
+            for p in m.transitions.iter_mut() {
+                p.1 += offset;
+            }
+            if nfa.is_accepting(i) {
+                m.transitions.push((None, final_idx));
+            }
+            self.nodes.push(m);
+        }
+        (offset + nfa.start_idx, self.node(vec![]))
+    }
+
+    fn then(&mut self, a: Fragment, b: Fragment) -> Fragment {
+        self.nodes[a.1].transitions.push((None, b.0));
+        (a.0, b.1)
+    }
+
+    fn or(&mut self, a: Fragment, b: Fragment) -> Fragment {
+        let start_idx = self.node(vec![(None, a.0), (None, b.0)]);
+        let final_idx = self.node(vec![]);
+        self.nodes[a.1].transitions.push((None, final_idx));
+        self.nodes[b.1].transitions.push((None, final_idx));
+        (start_idx, final_idx)
+    }
+
+    fn star(&mut self, a: Fragment) -> Fragment {
+        let final_idx = self.nodes.len() + 1;
+        let start_idx = self.node(vec![(None, a.0), (None, final_idx)]);
+        self.node(vec![]);
+        // looping back is listed first so that leftmost-first search is greedy
+        self.nodes[a.1].transitions.push((None, start_idx));
+        self.nodes[a.1].transitions.push((None, final_idx));
+        (start_idx, final_idx)
+    }
+
+    /// Wraps `a` in nodes that save the start and end of group `g`
+    fn capture(&mut self, a: Fragment, g: usize) -> Fragment {
+        let start_idx = self.node(vec![(None, a.0)]);
+        let final_idx = self.node(vec![]);
+        self.nodes[start_idx].save = Some(2 * g);
+        self.nodes[final_idx].save = Some(2 * g + 1);
+        self.nodes[a.1].transitions.push((None, final_idx));
+        (start_idx, final_idx)
+    }
+
+    fn finish(self, f: Fragment) -> NFA {
+        NFA::with_final(self.nodes, f.0, f.1)
+    }
+}
+
 fn main() {
 
     let r = Regex::Empty;
@@ -216,9 +655,62 @@ fn main() {
     println!("{:?}\n{:?}", s, t);
 }
 
+#[cfg(test)]
 mod test {
 
-    use super::{NFA, Regex};
+    use super::{DFA, LazyDFA, MatchKind, NFA, Regex};
+
+    /// All strings over `chars` of length at most `max_len`
+    pub fn strings(chars: &[char], max_len: usize) -> Vec<Vec<char>> {
+        let mut result = vec![vec![]];
+        let mut last = vec![vec![]];
+        for _ in 0..max_len {
+            let mut next = vec![];
+            for s in last.iter() {
+                for &c in chars {
+                    let mut t: Vec<char> = s.clone();
+                    t.push(c);
+                    next.push(t);
+                }
+            }
+            result.extend(next.iter().cloned());
+            last = next;
+        }
+        result
+    }
+
+    #[test]
+    fn test_nfa_nothing() {
+        let a = Regex::Single('a');
+        let n = NFA::from_regex(&Regex::Nothing);
+
+        assert!(!n.accepts(&[]));
+        assert!(!n.accepts(&['a']));
+
+        let n = NFA::from_regex(&Regex::Nothing.star());
+        assert!(n.accepts(&[]));
+        assert!(!n.accepts(&['a']));
+
+        let n = NFA::from_regex(&Regex::Nothing.or(&a));
+        assert!(n.accepts(&['a']));
+        assert!(!n.accepts(&[]));
+
+        let n = NFA::from_regex(&a.then(&Regex::Nothing));
+        assert!(!n.accepts(&['a']));
+    }
+
+    #[test]
+    fn test_nfa_empty() {
+        let a = Regex::Single('a');
+        let n = NFA::from_regex(&Regex::Empty);
+
+        assert!(n.accepts(&[]));
+        assert!(!n.accepts(&['a']));
+
+        let n = NFA::from_regex(&Regex::Empty.then(&a).then(&Regex::Empty));
+        assert!(n.accepts(&['a']));
+        assert!(!n.accepts(&[]));
+    }
 
     #[test]
     fn test_nfa_single() {
@@ -268,4 +760,252 @@ mod test {
         assert!(!n.accepts(&['c']));
         assert!(!n.accepts(&['a', 'c']));
     }
+
+    #[test]
+    fn test_nfa_class() {
+        let digit = Regex::class(&[('0', '9')], false);
+        let ident = Regex::class(&[('a', 'z'), ('A', 'Z'), ('_', '_')], false);
+        let r = ident.then(&ident.or(&digit).star());
+        let n = NFA::from_regex(&r);
+
+        assert!(n.accepts(&['x']));
+        assert!(n.accepts(&['_', 'F', 'o', 'o', '4', '2']));
+        assert!(!n.accepts(&['4', '2']));
+        assert!(!n.accepts(&['a', '-']));
+    }
+
+    #[test]
+    fn test_nfa_negated_class() {
+        let r = Regex::class(&[('a', 'c')], true).star();
+        let n = NFA::from_regex(&r);
+
+        assert!(n.accepts(&[]));
+        assert!(n.accepts(&['x', '\u{10FFFF}', '\0']));
+        assert!(!n.accepts(&['x', 'b']));
+    }
+
+    #[test]
+    fn test_nfa_union() {
+        let kw = NFA::from_regex(&Regex::parse("if|else").unwrap()).tagged(0);
+        let ident = NFA::from_regex(&Regex::parse("[a-z]+").unwrap()).tagged(1);
+        let untagged = NFA::from_regex(&Regex::parse("[0-9]+").unwrap());
+        let n = NFA::union(&[kw, ident, untagged]);
+
+        assert!(n.accepts(&['i', 'f']));
+        assert!(n.accepts(&['4', '2']));
+        assert!(!n.accepts(&['i', '4']));
+        assert_eq!(n.accepted_rule(&['i', 'f']), Some(0));
+        assert_eq!(n.accepted_rule(&['i', 'f', 's']), Some(1));
+        assert_eq!(n.accepted_rule(&['4', '2']), None);
+        assert_eq!(n.accepted_rule(&['i', '4']), None);
+
+        // accepting states of the union are wired up when it is embedded
+        let r = NFA::star(NFA::then(n, NFA::single(';')));
+        assert!(r.accepts(&['i', 'f', ';', '1', ';']));
+        assert!(!r.accepts(&['i', 'f', ';', '1']));
+    }
+
+    #[test]
+    fn test_nfa_from_dfa() {
+        let r = Regex::parse("(a|b)*abb").unwrap();
+        let dfa = DFA::from_nfa(&NFA::from_regex(&r).tagged(3)).minimize().0;
+        let n = NFA::from_dfa(&dfa);
+
+        assert!(n.accepts(&['b', 'a', 'b', 'b']));
+        assert!(!n.accepts(&['a', 'b', 'b', 'a']));
+        assert_eq!(n.accepted_rule(&['a', 'b', 'b']), Some(3));
+
+        let m = NFA::or(NFA::from_regex(&Regex::Single('c')), n);
+        assert!(m.accepts(&['c']));
+        assert!(m.accepts(&['a', 'b', 'b']));
+        assert!(!m.accepts(&['a', 'b']));
+    }
+
+    #[test]
+    fn test_nfa_plus_optional() {
+        let a = Regex::Single('a');
+        let b = Regex::Single('b');
+        let n = NFA::from_regex(&a.plus().then(&b.optional()));
+
+        assert!(n.accepts(&['a']));
+        assert!(n.accepts(&['a', 'a', 'b']));
+        assert!(!n.accepts(&[]));
+        assert!(!n.accepts(&['b']));
+        assert!(!n.accepts(&['a', 'b', 'b']));
+    }
+
+    #[test]
+    fn test_nfa_repeat() {
+        let a = Regex::Single('a');
+        let accepts_counts = |r: &Regex| {
+            let n = NFA::from_regex(r);
+            (0..6).filter(|&i| n.accepts(&vec!['a'; i])).collect::<Vec<usize>>()
+        };
+
+        assert_eq!(accepts_counts(&a.repeat(0)), vec![0]);
+        assert_eq!(accepts_counts(&a.repeat(3)), vec![3]);
+        assert_eq!(accepts_counts(&a.repeat_at_least(0)), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(accepts_counts(&a.repeat_at_least(2)), vec![2, 3, 4, 5]);
+        assert_eq!(accepts_counts(&a.repeat_between(0, 0)), vec![0]);
+        assert_eq!(accepts_counts(&a.repeat_between(0, 2)), vec![0, 1, 2]);
+        assert_eq!(accepts_counts(&a.repeat_between(2, 2)), vec![2]);
+        assert_eq!(accepts_counts(&a.repeat_between(1, 4)), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_repeat_between_invalid() {
+        Regex::Single('a').repeat_between(2, 1);
+    }
+
+    #[test]
+    fn test_nfa_any() {
+        let n = NFA::from_regex(&Regex::any().then(&Regex::Single('b')));
+
+        assert!(n.accepts(&['a', 'b']));
+        assert!(n.accepts(&['\n', 'b']));
+        assert!(!n.accepts(&['b']));
+        assert!(!n.accepts(&['a', 'b', 'b']));
+    }
+
+    #[test]
+    fn test_nfa_intersection_complement() {
+        let ident = Regex::parse("[a-z]+&~(?:if|else)").unwrap();
+        let n = NFA::from_regex(&ident);
+        assert!(n.accepts(&['i', 'f', 'f', 'y']));
+        assert!(n.accepts(&['e', 'l']));
+        assert!(!n.accepts(&['i', 'f']));
+        assert!(!n.accepts(&['e', 'l', 's', 'e']));
+        assert!(!n.accepts(&[]));
+
+        let n = NFA::from_regex(&Regex::parse("a~(?:b*)c").unwrap());
+        assert!(n.accepts(&['a', 'c', 'c']));
+        assert!(n.accepts(&['a', 'x', 'c']));
+        assert!(!n.accepts(&['a', 'c']));
+        assert!(!n.accepts(&['a', 'b', 'b', 'c']));
+
+        let all = NFA::from_regex(&Regex::Nothing.not());
+        assert!(all.accepts(&[]));
+        assert!(all.accepts(&['\u{10FFFF}', '\0']));
+        assert!(!NFA::from_regex(&Regex::any().star().not()).accepts(&[]));
+    }
+
+    #[test]
+    fn test_search_intersection_complement() {
+        let n = NFA::from_regex(&Regex::parse("[a-z]+&~(?:.*x.*)").unwrap());
+        assert_eq!(n.find("12 abxcd"), Some((3, 5)));
+        assert_eq!(n.find_at("12 abxcd", 0, MatchKind::LeftmostFirst), Some((3, 5)));
+        assert_eq!(LazyDFA::new(&n, 1 << 20).find("12 abxcd"), Some((3, 5)));
+        assert_eq!(n.find_iter("abxcd").collect::<Vec<_>>(), vec![(0, 2), (3, 5)]);
+
+        let r = Regex::parse("(a+)(~(?:.*b.*))").unwrap();
+        assert_eq!(r.captures("caab", MatchKind::LeftmostLongest), Some(vec![Some((1, 3)), Some((1, 3)), Some((3, 3))]));
+    }
+
+    #[test]
+    fn test_nfa_reverse() {
+        let n = NFA::from_regex(&Regex::parse("a(b|cd)*e?").unwrap());
+        let r = n.reverse();
+        for s in strings(&['a', 'b', 'c', 'd', 'e'], 5) {
+            let mut reversed = s.clone();
+            reversed.reverse();
+            assert_eq!(r.accepts(&reversed), n.accepts(&s), "{:?}", s);
+        }
+        assert!(r.reverse().accepts(&['a', 'c', 'd', 'b']));
+
+        // every accepting state of a union is a way in
+        let u = NFA::union(&[NFA::single('a').tagged(0), NFA::from_regex(&Regex::parse("bc").unwrap()).tagged(1)]);
+        let r = u.reverse();
+        assert!(r.accepts(&['a']));
+        assert!(r.accepts(&['c', 'b']));
+        assert!(!r.accepts(&['b', 'c']));
+        assert_eq!(r.accepted_rule(&['a']), None);
+    }
+
+    #[test]
+    fn test_consuming_builders() {
+        let a = Regex::Single('a');
+        let b = Regex::Single('b');
+
+        assert_eq!(a.clone().into_or(b.clone()), a.or(&b));
+        assert_eq!(a.clone().into_then(b.clone()), a.then(&b));
+        assert_eq!(a.clone().into_star(), a.star());
+        assert_eq!(Regex::alternation(vec![a.clone(), b.clone(), a.clone()]), a.or(&b).or(&a));
+        assert_eq!(Regex::concatenation(vec![a.clone(), b.clone(), a.clone()]), a.then(&b).then(&a));
+        assert_eq!(Regex::alternation(vec![a.clone()]), a);
+        assert_eq!(Regex::alternation(vec![]), Regex::Nothing);
+        assert_eq!(Regex::concatenation(vec![]), Regex::Empty);
+    }
+
+    #[test]
+    fn test_large_alternation() {
+        let words: Vec<String> = (0..1000).map(|i| i.to_string()).collect();
+        let r = Regex::alternation(words.iter().map(|w| Regex::concatenation(w.chars().map(Regex::Single))));
+
+        assert_eq!(r, Regex::parse(&words.join("|")).unwrap());
+    }
+
+    #[test]
+    fn test_large_alternation_moves_operands() {
+        // cloning the alternation built so far at each step took quadratic
+        // time, and would have copied the operands to new addresses
+        fn operand(r: &Regex) -> *const Regex {
+            match *r {
+                Regex::Star(ref a) => &**a,
+                _ => panic!("not a star: {:?}", r),
+            }
+        }
+        let parts: Vec<Regex> = (0..1000).map(|_| Regex::Single('a').into_star()).collect();
+        let addresses: Vec<*const Regex> = parts.iter().map(operand).collect();
+        let r = Regex::alternation(parts);
+
+        let mut found = vec![];
+        let mut rest = &r;
+        while let Regex::Or(ref a, ref b) = *rest {
+            found.push(operand(b));
+            rest = a;
+        }
+        found.push(operand(rest));
+        found.reverse();
+        assert!(found == addresses);
+    }
+
+    #[test]
+    fn test_nfa_long_optional_chain() {
+        // every node's epsilon closure reaches most of the others, so
+        // precomputing all of them would take quadratic time and memory
+        let n = NFA::from_regex(&Regex::Single('a').optional().repeat(4000));
+        assert!(n.accepts(&[]));
+        assert!(n.accepts(&['a'; 10]));
+        assert!(!n.accepts(&['a', 'b']));
+
+        let n = NFA::from_regex(&Regex::Single('a').optional().repeat(200));
+        assert!(n.accepts(&['a'; 200]));
+        assert!(!n.accepts(&['a'; 201]));
+    }
+
+    #[test]
+    fn test_from_regex_deep() {
+        let word: Vec<char> = "abc".chars().cycle().take(100000).collect();
+        let n = NFA::from_regex(&Regex::concatenation(word.iter().map(|&c| Regex::Single(c))));
+        assert!(n.accepts(&word));
+        assert!(!n.accepts(&word[1..]));
+
+        // leaning the other way, with groups
+        let mut r = Regex::Single('a');
+        for i in 0..100000 {
+            r = Regex::Single('b').into_then(if i % 1000 > 0 { r } else { Regex::Capture(1, Box::new(r)) });
+        }
+        let mut bs = vec!['b'; 100000];
+        bs.push('a');
+        let n = NFA::from_regex(&r);
+        assert!(n.accepts(&bs));
+        assert!(!n.accepts(&bs[1..]));
+
+        let mut r = Regex::Single('a');
+        for _ in 0..100000 {
+            r = r.into_star().into_or(Regex::Empty);
+        }
+        assert_eq!(NFA::from_regex(&r).nodes.len(), 2 + 100000 * 6);
+    }
 }
@@ -0,0 +1,645 @@
+use std::error::Error;
+use std::fmt;
+
+use super::{CharClass, Regex};
+
+/// Why a pattern could not be parsed
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum ParseErrorKind {
+    /// A `*`, `+`, `?` or `{` with no preceding expression to apply it to
+    NothingToRepeat,
+    /// A `~` with no following expression to apply it to
+    NothingToComplement,
+    /// A `(` with no matching `)`
+    UnclosedGroup,
+    /// A `)` with no matching `(`
+    UnopenedGroup,
+    /// A `\` at the very end of the pattern
+    TrailingEscape,
+    /// A `(?` not followed by `:`
+    UnsupportedGroup,
+    /// A `[` with no matching `]`
+    UnclosedClass,
+    /// A class range such as `z-a` whose bounds are out of order
+    InvalidRange,
+    /// A `{` not followed by `n}`, `n,}` or `n,m}` with `n <= m`
+    InvalidRepetition,
+    /// A bounded repetition with a count above 1000, or a bounded
+    /// repetition or `+` which would expand to more than 100000 nodes
+    RepetitionTooLarge,
+    /// Groups or complements nested more than 250 deep
+    NestingTooDeep,
+    /// A pattern whose regex would be more than 2500 deep, where each part
+    /// of a chain of concatenations, alternations or intersections is one
+    /// level deeper than the next
+    RegexTooDeep,
+}
+
+/// The largest count allowed in a bounded repetition
+const MAX_REPETITION: usize = 1000;
+
+/// The most nodes a bounded repetition or `+` may expand to, which also
+/// stops nested repetitions multiplying out
+const MAX_REPETITION_SIZE: usize = 100_000;
+
+/// The deepest groups and complements may nest, as each level takes several
+/// calls of the recursive descent
+const MAX_NESTING: usize = 250;
+
+/// The deepest a parsed regex may be. Most operations on regexes recurse
+/// once per level, and this leaves room for them on an 8 MiB stack.
+const MAX_DEPTH: usize = 2500;
+
+/// A malformed pattern, with the byte offset at which the problem was found
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct ParseError {
+    pub offset: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self.kind {
+            ParseErrorKind::NothingToRepeat => "repetition operator with nothing to repeat",
+            ParseErrorKind::NothingToComplement => "complement operator with nothing to complement",
+            ParseErrorKind::UnclosedGroup => "unclosed group",
+            ParseErrorKind::UnopenedGroup => "unopened group",
+            ParseErrorKind::TrailingEscape => "trailing escape character",
+            ParseErrorKind::UnsupportedGroup => "unsupported group syntax",
+            ParseErrorKind::UnclosedClass => "unclosed character class",
+            ParseErrorKind::InvalidRange => "invalid character class range",
+            ParseErrorKind::InvalidRepetition => "invalid bounded repetition",
+            ParseErrorKind::RepetitionTooLarge => "bounded repetition too large",
+            ParseErrorKind::NestingTooDeep => "groups or complements nested too deeply",
+            ParseErrorKind::RegexTooDeep => "pattern too deep",
+        };
+        write!(f, "{} at offset {}", reason, self.offset)
+    }
+}
+
+impl Error for ParseError {}
+
+impl Regex {
+
+    /// Parses the usual concrete syntax: alternation `|`, juxtaposition for
+    /// concatenation, postfix `*`, `+`, `?`, `{n}`, `{n,}` and `{n,m}`, `.`
+    /// for any character, capture groups `(...)` numbered from 1 in order of
+    /// their opening parenthesis, non-capturing groups `(?:...)` and `\` to escape
+    /// the next character. Binary operators associate to the left, so `abc`
+    /// parses as `a.then(&b).then(&c)`. An empty pattern or alternative
+    /// denotes `Regex::Empty`. Character classes are written `[a-z_]`, or
+    /// `[^a-z_]` for their negation, and `[]` is the empty class.
+    ///
+    /// Intersection `&` binds tighter than alternation but looser than
+    /// concatenation, and prefix complement `~` binds tighter than
+    /// concatenation but looser than repetition, so `~ab*&c|d` means
+    /// `(?:(?:(?:~a)(?:b*))&c)|d`.
+    ///
+    /// Bounded repetitions and `+` are expanded into copies of their operand,
+    /// so counts are limited to 1000 and expansions to 100000 nodes.
+    /// `Regex::repeat`, `Regex::plus` and the like have no such limits.
+    /// Groups and complements may nest at most 250 deep, and the regex
+    /// itself may be at most 2500 deep, so that operations which recurse
+    /// over it do not overflow the stack. That limits a chain such as `abc`
+    /// to 2500 parts.
+    pub fn parse(pattern: &str) -> Result<Regex, ParseError> {
+        let mut p = Parser { src: pattern, pos: 0, groups: 0, nesting: 0 };
+        let (r, _) = p.alternation()?;
+        match p.peek() {
+            None => Ok(r),
+            // alternation only stops early on an unmatched close paren
+            Some(_) => Err(p.error(ParseErrorKind::UnopenedGroup)),
+        }
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    /// Byte offset of the next unconsumed character
+    pos: usize,
+    /// The number of capture groups opened so far
+    groups: usize,
+    /// The number of groups and complements enclosing the next character
+    nesting: usize,
+}
+
+/// A parsed regex and its depth, counting a leaf as 1
+type Parsed = (Regex, usize);
+
+impl<'a> Parser<'a> {
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        if let Some(c) = c {
+            self.pos += c.len_utf8();
+        }
+        c
+    }
+
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError { offset: self.pos, kind: kind }
+    }
+
+    /// Enters a group or complement whose first character is at byte offset
+    /// `start`, failing if that would nest too deeply
+    fn enter(&mut self, start: usize) -> Result<(), ParseError> {
+        if self.nesting == MAX_NESTING {
+            return Err(ParseError { offset: start, kind: ParseErrorKind::NestingTooDeep });
+        }
+        self.nesting += 1;
+        Ok(())
+    }
+
+    /// Returns `depth`, or fails if that is too deep for a regex made by the
+    /// operator or operand at byte offset `offset`
+    fn within_depth(&self, depth: usize, offset: usize) -> Result<usize, ParseError> {
+        if depth > MAX_DEPTH {
+            return Err(ParseError { offset: offset, kind: ParseErrorKind::RegexTooDeep });
+        }
+        Ok(depth)
+    }
+
+    fn alternation(&mut self) -> Result<Parsed, ParseError> {
+        let (mut r, mut d) = self.intersection()?;
+        while self.peek() == Some('|') {
+            let start = self.pos;
+            self.bump();
+            let (s, e) = self.intersection()?;
+            r = Regex::Or(Box::new(r), Box::new(s));
+            d = self.within_depth(d.max(e) + 1, start)?;
+        }
+        Ok((r, d))
+    }
+
+    fn intersection(&mut self) -> Result<Parsed, ParseError> {
+        let (mut r, mut d) = self.concatenation()?;
+        while self.peek() == Some('&') {
+            let start = self.pos;
+            self.bump();
+            let (s, e) = self.concatenation()?;
+            r = Regex::And(Box::new(r), Box::new(s));
+            d = self.within_depth(d.max(e) + 1, start)?;
+        }
+        Ok((r, d))
+    }
+
+    fn concatenation(&mut self) -> Result<Parsed, ParseError> {
+        let mut r: Option<Parsed> = None;
+        loop {
+            let start = self.pos;
+            match self.peek() {
+                None | Some('|') | Some('&') | Some(')') => break,
+                _ => {}
+            }
+            let (s, e) = self.complement()?;
+            r = Some(match r {
+                None => (s, e),
+                Some((r, d)) => (Regex::Then(Box::new(r), Box::new(s)), self.within_depth(d.max(e) + 1, start)?),
+            });
+        }
+        Ok(r.unwrap_or((Regex::Empty, 1)))
+    }
+
+    fn complement(&mut self) -> Result<Parsed, ParseError> {
+        if self.peek() != Some('~') {
+            return self.repetition();
+        }
+        let start = self.pos;
+        self.bump();
+        match self.peek() {
+            None | Some('|') | Some('&') | Some(')') => {
+                Err(ParseError { offset: start, kind: ParseErrorKind::NothingToComplement })
+            },
+            _ => {
+                self.enter(start)?;
+                let (r, d) = self.complement()?;
+                self.nesting -= 1;
+                Ok((Regex::Not(Box::new(r)), self.within_depth(d + 1, start)?))
+            },
+        }
+    }
+
+    fn repetition(&mut self) -> Result<Parsed, ParseError> {
+        let (mut r, mut d) = self.atom()?;
+        loop {
+            let start = self.pos;
+            let op = match self.peek() {
+                Some(c) if "*+?{".contains(c) => c,
+                _ => break,
+            };
+            self.bump();
+            let too_large = ParseError { offset: start, kind: ParseErrorKind::RepetitionTooLarge };
+            let (s, e) = match op {
+                '*' => (Regex::Star(Box::new(r)), d + 1),
+                // r+ is rr*, so nested ones double in size
+                '+' => {
+                    if size_within(&r, MAX_REPETITION_SIZE / 2).is_none() {
+                        return Err(too_large);
+                    }
+                    (r.plus(), d + 2)
+                },
+                '?' => (r.optional(), d + 1),
+                _ => {
+                    let (n, m) = self.bounds(start)?;
+                    // {n,} makes n copies and a star of one more
+                    let copies = m.unwrap_or(n + 1);
+                    if n > MAX_REPETITION || m.is_some_and(|m| m > MAX_REPETITION) {
+                        return Err(too_large);
+                    }
+                    if copies > 0 && size_within(&r, MAX_REPETITION_SIZE / copies).is_none() {
+                        return Err(too_large);
+                    }
+                    let s = match m {
+                        Some(m) => r.repeat_between(n, m),
+                        None => r.repeat_at_least(n),
+                    };
+                    let e = depth(&s);
+                    (s, e)
+                },
+            };
+            r = s;
+            d = self.within_depth(e, start)?;
+        }
+        Ok((r, d))
+    }
+
+    /// Parses the remainder of a bounded repetition whose `{` is at byte
+    /// offset `start`, returning the lower and (if any) upper bound
+    fn bounds(&mut self, start: usize) -> Result<(usize, Option<usize>), ParseError> {
+        let invalid = ParseError { offset: start, kind: ParseErrorKind::InvalidRepetition };
+        let n = self.number().ok_or(invalid.clone())?;
+        let m = if self.peek() == Some(',') {
+            self.bump();
+            if self.peek() == Some('}') { None } else { Some(self.number().ok_or(invalid.clone())?) }
+        } else {
+            Some(n)
+        };
+        if self.bump() != Some('}') || m.is_some_and(|m| m < n) {
+            return Err(invalid);
+        }
+        Ok((n, m))
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.bump();
+        }
+        self.src[start..self.pos].parse().ok()
+    }
+
+    fn atom(&mut self) -> Result<Parsed, ParseError> {
+        let start = self.pos;
+        match self.bump() {
+            Some('(') => {
+                let group = if self.src[self.pos..].starts_with('?') {
+                    if !self.src[self.pos..].starts_with("?:") {
+                        return Err(ParseError { offset: start, kind: ParseErrorKind::UnsupportedGroup });
+                    }
+                    self.pos += 2;
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                self.enter(start)?;
+                let (r, d) = self.alternation()?;
+                self.nesting -= 1;
+                if self.bump() != Some(')') {
+                    return Err(ParseError { offset: start, kind: ParseErrorKind::UnclosedGroup });
+                }
+                Ok(match group {
+                    Some(g) => (Regex::Capture(g, Box::new(r)), self.within_depth(d + 1, start)?),
+                    None => (r, d),
+                })
+            },
+            Some('[') => Ok((self.class(start)?, 1)),
+            Some('.') => Ok((Regex::any(), 1)),
+            Some('*') | Some('+') | Some('?') | Some('{') => Err(ParseError { offset: start, kind: ParseErrorKind::NothingToRepeat }),
+            Some('\\') => match self.bump() {
+                Some(c) => Ok((Regex::Single(unescape(c)), 1)),
+                None => Err(ParseError { offset: start, kind: ParseErrorKind::TrailingEscape }),
+            },
+            Some(c) => Ok((Regex::Single(c), 1)),
+            None => unreachable!("atom called at end of pattern"),
+        }
+    }
+
+    /// Parses the remainder of a class whose `[` is at byte offset `start`
+    fn class(&mut self, start: usize) -> Result<Regex, ParseError> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.bump();
+        }
+        let mut ranges = vec![];
+        loop {
+            let lo_offset = self.pos;
+            let lo = match self.class_char(start)? {
+                Some(c) => c,
+                None => break,
+            };
+            let mut hi = lo;
+            // a '-' just before the closing ']' is a literal
+            if self.peek() == Some('-') && !self.src[self.pos + 1..].starts_with(']') {
+                self.bump();
+                hi = match self.class_char(start)? {
+                    Some(c) => c,
+                    None => return Err(ParseError { offset: start, kind: ParseErrorKind::UnclosedClass }),
+                };
+                if hi < lo {
+                    return Err(ParseError { offset: lo_offset, kind: ParseErrorKind::InvalidRange });
+                }
+            }
+            ranges.push((lo, hi));
+        }
+        Ok(Regex::Class(CharClass::new(&ranges, negated)))
+    }
+
+    /// The next character of a class, or None if the class has ended
+    fn class_char(&mut self, start: usize) -> Result<Option<char>, ParseError> {
+        let escape_offset = self.pos;
+        match self.bump() {
+            Some(']') => Ok(None),
+            Some('\\') => match self.bump() {
+                Some(c) => Ok(Some(unescape(c))),
+                None => Err(ParseError { offset: escape_offset, kind: ParseErrorKind::TrailingEscape }),
+            },
+            Some(c) => Ok(Some(c)),
+            None => Err(ParseError { offset: start, kind: ParseErrorKind::UnclosedClass }),
+        }
+    }
+}
+
+/// Maps the character following a `\` to the character it denotes
+fn unescape(c: char) -> char {
+    match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        c => c,
+    }
+}
+
+/// The number of nodes on the longest path from the root of `r` to a leaf
+fn depth(r: &Regex) -> usize {
+    let mut deepest = 0;
+    let mut stack = vec![(r, 1)];
+    while let Some((r, d)) = stack.pop() {
+        deepest = deepest.max(d);
+        match *r {
+            Regex::Nothing | Regex::Empty | Regex::Single(_) | Regex::Class(_) => {},
+            Regex::Or(ref a, ref b) | Regex::Then(ref a, ref b) | Regex::And(ref a, ref b) => {
+                stack.push((a, d + 1));
+                stack.push((b, d + 1));
+            },
+            Regex::Star(ref a) | Regex::Capture(_, ref a) | Regex::Not(ref a) => stack.push((a, d + 1)),
+        }
+    }
+    deepest
+}
+
+/// The number of nodes in `r`, or None if that is more than `limit`
+fn size_within(r: &Regex, limit: usize) -> Option<usize> {
+    let mut size = 0;
+    let mut stack = vec![r];
+    while let Some(r) = stack.pop() {
+        size += 1;
+        if size > limit {
+            return None;
+        }
+        match *r {
+            Regex::Nothing | Regex::Empty | Regex::Single(_) | Regex::Class(_) => {},
+            Regex::Or(ref a, ref b) | Regex::Then(ref a, ref b) | Regex::And(ref a, ref b) => {
+                stack.push(a);
+                stack.push(b);
+            },
+            Regex::Star(ref a) | Regex::Capture(_, ref a) | Regex::Not(ref a) => stack.push(a),
+        }
+    }
+    Some(size)
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::thread;
+
+    use super::{ParseError, ParseErrorKind};
+    use super::super::{NFA, Regex};
+
+    fn parses_to(pattern: &str, expected: &Regex) {
+        let r = Regex::parse(pattern).unwrap();
+        assert_eq!(format!("{:?}", r), format!("{:?}", expected));
+    }
+
+    fn fails_with(pattern: &str, offset: usize, kind: ParseErrorKind) {
+        assert_eq!(Regex::parse(pattern).unwrap_err(), ParseError { offset: offset, kind: kind });
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        let a = Regex::Single('a');
+        let b = Regex::Single('b');
+        let c = Regex::Single('c');
+
+        parses_to("a", &a);
+        parses_to("abc", &a.then(&b).then(&c));
+        parses_to("a|b|c", &a.or(&b).or(&c));
+        parses_to("ab|c", &a.then(&b).or(&c));
+        parses_to("ab*", &a.then(&b.star()));
+        parses_to("(?:a|b)*c", &a.or(&b).star().then(&c));
+        parses_to("a**", &a.star().star());
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        let a = Regex::Single('a');
+
+        parses_to("", &Regex::Empty);
+        parses_to("(?:)", &Regex::Empty);
+        parses_to("a|", &a.or(&Regex::Empty));
+        parses_to("|a", &Regex::Empty.or(&a));
+    }
+
+    #[test]
+    fn test_parse_escapes() {
+        parses_to("\\*", &Regex::Single('*'));
+        parses_to("\\(\\|", &Regex::Single('(').then(&Regex::Single('|')));
+        parses_to("\\n\\t", &Regex::Single('\n').then(&Regex::Single('\t')));
+        parses_to("\\\\", &Regex::Single('\\'));
+    }
+
+    #[test]
+    fn test_parse_class() {
+        let a = Regex::Single('a');
+
+        parses_to("[a-z_]", &Regex::class(&[('a', 'z'), ('_', '_')], false));
+        parses_to("[^0-9]a", &Regex::class(&[('0', '9')], true).then(&a));
+        parses_to("[-a-]", &Regex::class(&[('-', '-'), ('a', 'a'), ('-', '-')], false));
+        parses_to("[\\]\\-]", &Regex::class(&[(']', ']'), ('-', '-')], false));
+        parses_to("[|*(]", &Regex::class(&[('|', '|'), ('*', '*'), ('(', '(')], false));
+        parses_to("[]", &Regex::class(&[], false));
+        parses_to("[^]", &Regex::class(&[], true));
+    }
+
+    #[test]
+    fn test_parse_groups() {
+        let a = Regex::Single('a');
+        let b = Regex::Single('b');
+
+        parses_to("(a)", &a.capture(1));
+        parses_to("()", &Regex::Empty.capture(1));
+        parses_to("(a)|(b)", &a.capture(1).or(&b.capture(2)));
+        parses_to("((a)b)*", &a.capture(2).then(&b).capture(1).star());
+        parses_to("(?:(a)|b)(b)", &a.capture(1).or(&b).then(&b.capture(2)));
+        parses_to("(?:(?:a))", &a);
+    }
+
+    #[test]
+    fn test_parse_repetition() {
+        let a = Regex::Single('a');
+        let b = Regex::Single('b');
+
+        parses_to("a+", &a.plus());
+        parses_to("ab?", &a.then(&b.optional()));
+        parses_to("a{3}", &a.repeat(3));
+        parses_to("a{0}", &Regex::Empty);
+        parses_to("a{2,}", &a.repeat_at_least(2));
+        parses_to("a{1,3}", &a.repeat_between(1, 3));
+        parses_to("a{0,0}", &Regex::Empty);
+        parses_to("a*?", &a.star().optional());
+        parses_to(".", &Regex::any());
+        parses_to("\\.\\+\\?\\{", &Regex::Single('.').then(&Regex::Single('+'))
+            .then(&Regex::Single('?')).then(&Regex::Single('{')));
+        parses_to("a}", &a.then(&Regex::Single('}')));
+    }
+
+    #[test]
+    fn test_parse_intersection_complement() {
+        let a = Regex::Single('a');
+        let b = Regex::Single('b');
+        let c = Regex::Single('c');
+
+        parses_to("a&b&c", &a.and(&b).and(&c));
+        parses_to("a|b&c", &a.or(&b.and(&c)));
+        parses_to("ab&c", &a.then(&b).and(&c));
+        parses_to("~ab", &a.not().then(&b));
+        parses_to("~a*", &a.star().not());
+        parses_to("~~a", &a.not().not());
+        parses_to("~(?:ab)&c", &a.then(&b).not().and(&c));
+        parses_to("a&", &a.and(&Regex::Empty));
+        parses_to("\\&\\~[&~]", &Regex::Single('&').then(&Regex::Single('~'))
+            .then(&Regex::class(&[('&', '&'), ('~', '~')], false)));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        fails_with("*", 0, ParseErrorKind::NothingToRepeat);
+        fails_with("+", 0, ParseErrorKind::NothingToRepeat);
+        fails_with("a|?", 2, ParseErrorKind::NothingToRepeat);
+        fails_with("{2}", 0, ParseErrorKind::NothingToRepeat);
+        fails_with("a|*", 2, ParseErrorKind::NothingToRepeat);
+        fails_with("(*)", 1, ParseErrorKind::NothingToRepeat);
+        fails_with("~", 0, ParseErrorKind::NothingToComplement);
+        fails_with("a~|b", 1, ParseErrorKind::NothingToComplement);
+        fails_with("(~)", 1, ParseErrorKind::NothingToComplement);
+        fails_with("~&a", 0, ParseErrorKind::NothingToComplement);
+        fails_with("~*", 1, ParseErrorKind::NothingToRepeat);
+        fails_with("a(b", 1, ParseErrorKind::UnclosedGroup);
+        fails_with("a)b", 1, ParseErrorKind::UnopenedGroup);
+        fails_with("a(?:b", 1, ParseErrorKind::UnclosedGroup);
+        fails_with("a(?b)", 1, ParseErrorKind::UnsupportedGroup);
+        fails_with("(?", 0, ParseErrorKind::UnsupportedGroup);
+        fails_with("ab\\", 2, ParseErrorKind::TrailingEscape);
+        fails_with("é)", 2, ParseErrorKind::UnopenedGroup);
+        fails_with("a[bc", 1, ParseErrorKind::UnclosedClass);
+        fails_with("[a-", 0, ParseErrorKind::UnclosedClass);
+        fails_with("[\\", 1, ParseErrorKind::TrailingEscape);
+        fails_with("[az-a]", 2, ParseErrorKind::InvalidRange);
+        fails_with("a{", 1, ParseErrorKind::InvalidRepetition);
+        fails_with("a{}", 1, ParseErrorKind::InvalidRepetition);
+        fails_with("a{,2}", 1, ParseErrorKind::InvalidRepetition);
+        fails_with("ab{2,1}", 2, ParseErrorKind::InvalidRepetition);
+        fails_with("a{1,x}", 1, ParseErrorKind::InvalidRepetition);
+        fails_with("a{99999999999999999999999}", 1, ParseErrorKind::InvalidRepetition);
+    }
+
+    #[test]
+    fn test_parse_repetition_limits() {
+        assert!(Regex::parse("a{1000}").is_ok());
+        assert!(Regex::parse("(?:ab){0,1000}").is_ok());
+        assert!(Regex::parse("(?:a{10}){100}").is_ok());
+        assert!(Regex::parse("a{0}{4000000000}").is_err());
+
+        fails_with("a{1001}", 1, ParseErrorKind::RepetitionTooLarge);
+        fails_with("a{2,1001}", 1, ParseErrorKind::RepetitionTooLarge);
+        fails_with("a{1001,}", 1, ParseErrorKind::RepetitionTooLarge);
+        fails_with("a{4000000000}", 1, ParseErrorKind::RepetitionTooLarge);
+        // each count is allowed, but together they multiply out
+        fails_with("(?:a{1000}){1000}", 11, ParseErrorKind::RepetitionTooLarge);
+        fails_with("(?:(?:ab){100}){100}{100}", 20, ParseErrorKind::RepetitionTooLarge);
+
+        // so do nested pluses, each of which doubles its operand
+        let nested = |n: usize| format!("{}a{}", "(?:".repeat(n), ")+".repeat(n));
+        assert!(Regex::parse(&nested(10)).is_ok());
+        fails_with(&nested(24), 104, ParseErrorKind::RepetitionTooLarge);
+    }
+
+    #[test]
+    fn test_parse_nesting_limit() {
+        let groups = |n: usize| format!("{}a{}", "(?:".repeat(n), ")".repeat(n));
+        assert!(Regex::parse(&groups(250)).is_ok());
+        assert!(Regex::parse(&format!("{}a", "~".repeat(250))).is_ok());
+
+        fails_with(&groups(251), 750, ParseErrorKind::NestingTooDeep);
+        // these overflowed the stack rather than failing
+        fails_with(&"(".repeat(50000), 250, ParseErrorKind::NestingTooDeep);
+        fails_with(&format!("{}a", "~".repeat(50000)), 250, ParseErrorKind::NestingTooDeep);
+        fails_with(&"~(".repeat(50000), 250, ParseErrorKind::NestingTooDeep);
+    }
+
+    #[test]
+    fn test_parse_depth_limit() {
+        let chain = "a".repeat(2500);
+        fails_with(&format!("{}a", chain), 2500, ParseErrorKind::RegexTooDeep);
+        fails_with(&"a|".repeat(3000), 4999, ParseErrorKind::RegexTooDeep);
+        fails_with(&format!("(?:{})*", chain), 2504, ParseErrorKind::RegexTooDeep);
+
+        // the deepest regexes allowed are still safe to work with on the
+        // stack of a main thread
+        let patterns = [chain, "(?:ab){0,1000}".to_string(), "a|".repeat(2499)];
+        thread::Builder::new().stack_size(8 << 20).spawn(move || {
+            for pattern in patterns.iter() {
+                let r = Regex::parse(pattern).unwrap();
+                assert_eq!(r.clone(), r);
+                assert!(r.to_string().len() >= pattern.len());
+                assert_eq!(r.simplify().nullable(), r.nullable());
+                assert_eq!(r.canonical().canonical(), r.canonical());
+                assert_eq!(r.derivative('a').nullable(), NFA::from_regex(&r).accepts(&['a']));
+            }
+        }).unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_parse_nfa() {
+        let n = NFA::from_regex(&Regex::parse("(a|b)*abb").unwrap());
+
+        assert!(n.accepts(&['a', 'b', 'b']));
+        assert!(n.accepts(&['b', 'a', 'a', 'b', 'b']));
+        assert!(!n.accepts(&['a', 'b']));
+        assert!(!n.accepts(&['a', 'b', 'b', 'a']));
+    }
+
+    #[test]
+    fn test_parse_class_nfa() {
+        let n = NFA::from_regex(&Regex::parse("[a-zA-Z_][a-zA-Z_0-9]*").unwrap());
+
+        assert!(n.accepts(&['f', 'o', 'o', '_', '1']));
+        assert!(!n.accepts(&['1', 'f']));
+    }
+}
@@ -0,0 +1,52 @@
+/// A small xorshift64* generator, so that sampling and randomized tests are
+/// reproducible from their seed without depending on an external crate
+#[derive(Debug,Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+
+    pub fn new(seed: u64) -> Rng {
+        // the state must never be zero, as it would then stay zero
+        Rng { state: (seed ^ 0x9E37_79B9_7F4A_7C15) | 1 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A number below `n`, which must be positive
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// One of `xs`, which must be non-empty
+    pub fn choose<'a, T>(&mut self, xs: &'a [T]) -> &'a T {
+        &xs[self.below(xs.len())]
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::Rng;
+
+    #[test]
+    fn test_rng_reproducible() {
+        let xs: Vec<u64> = (0..5).map({ let mut r = Rng::new(7); move |_| r.next_u64() }).collect();
+        let ys: Vec<u64> = (0..5).map({ let mut r = Rng::new(7); move |_| r.next_u64() }).collect();
+        assert_eq!(xs, ys);
+        assert!(Rng::new(0).next_u64() != 0);
+
+        let mut r = Rng::new(1);
+        let mut seen = [false; 6];
+        for _ in 0..100 {
+            seen[r.below(6)] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+    }
+}
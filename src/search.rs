@@ -0,0 +1,235 @@
+use std::mem;
+
+use super::NFA;
+use super::sparse::SparseSet;
+
+/// Which match to report when several start at the leftmost position
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum MatchKind {
+    /// The longest match, as POSIX tools and lexers do
+    LeftmostLongest,
+    /// The match a backtracking matcher would find first, preferring earlier
+    /// alternatives and more iterations of a star, as Perl does
+    LeftmostFirst,
+}
+
+/// Unanchored search, reporting matches as `(start, end)` byte spans
+impl NFA {
+
+    /// The leftmost-longest match in `haystack`
+    pub fn find(&self, haystack: &str) -> Option<(usize, usize)> {
+        self.find_at(haystack, 0, MatchKind::LeftmostLongest)
+    }
+
+    /// The leftmost match in `haystack` starting at or after byte offset
+    /// `start`, which must lie on a character boundary.
+    ///
+    /// This is a single left-to-right pass, in O(n·m) for n characters and m
+    /// nodes and transitions. Each thread is a state and the position its
+    /// match began at. Until a match is found, a thread for the start state
+    /// is added behind the others at each position, so threads stay in
+    /// priority order: by start, then by the order of transitions out of each
+    /// node. Of two threads reaching the same state only the first is kept,
+    /// as both have the same future, and threads that began after the match
+    /// found so far are dropped. With `LeftmostFirst`, a thread in an
+    /// accepting state also drops those of lower priority, after stepping on
+    /// itself: accepting states of NFAs built from DFAs have transitions of
+    /// their own, and continuing is preferred to stopping, as a star prefers
+    /// another iteration.
+    pub fn find_at(&self, haystack: &str, start: usize, kind: MatchKind) -> Option<(usize, usize)> {
+        let mut threads = vec![];
+        let mut next = vec![];
+        let mut seen = SparseSet::new(self.nodes.len());
+        let mut matched: Option<(usize, usize)> = None;
+        let mut pos = start;
+        let mut chars = haystack[start..].chars();
+        loop {
+            // seen holds the states of threads, so a new match starting here
+            // only adds the states no earlier thread has reached
+            if matched.is_none() {
+                self.add_thread(&mut threads, &mut seen, self.start_idx, pos);
+            }
+            if threads.is_empty() {
+                break;
+            }
+            let c = chars.next();
+            next.clear();
+            seen.clear();
+            for &(t, from) in threads.iter() {
+                if matched.is_some_and(|(s, _)| from > s) {
+                    break;
+                }
+                if let Some(c) = c {
+                    for &(label, u) in self.nodes[t].transitions.iter() {
+                        match label {
+                            Some((lo, hi)) if lo <= c && c <= hi => self.add_thread(&mut next, &mut seen, u, from),
+                            _ => {}
+                        }
+                    }
+                }
+                if self.is_accepting(t) {
+                    matched = Some((from, pos));
+                    if kind == MatchKind::LeftmostFirst {
+                        break;
+                    }
+                }
+            }
+            match c {
+                Some(c) => pos += c.len_utf8(),
+                None => break,
+            }
+            mem::swap(&mut threads, &mut next);
+        }
+        matched
+    }
+
+    /// All non-overlapping leftmost-longest matches in `haystack`
+    pub fn find_iter<'a>(&'a self, haystack: &'a str) -> FindIter<'a> {
+        self.find_iter_with(haystack, MatchKind::LeftmostLongest)
+    }
+
+    pub fn find_iter_with<'a>(&'a self, haystack: &'a str, kind: MatchKind) -> FindIter<'a> {
+        FindIter {
+            nfa: self,
+            haystack: haystack,
+            kind: kind,
+            pos: 0,
+            last_end: None,
+        }
+    }
+
+    /// Appends `s` and the states reachable from it by e-steps to `threads`
+    /// in depth first order, as threads following a match that began at
+    /// `start`, skipping any already seen
+    fn add_thread(&self, threads: &mut Vec<(usize, usize)>, seen: &mut SparseSet, s: usize, start: usize) {
+        let mut stack = vec![s];
+        while let Some(s) = stack.pop() {
+            if seen.insert(s) {
+                threads.push((s, start));
+                // reversed so that the first transition is explored first
+                for &(label, t) in self.nodes[s].transitions.iter().rev() {
+                    if label.is_none() {
+                        stack.push(t);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// An iterator over the non-overlapping matches in a string. An empty match
+/// immediately after the previous match is skipped.
+pub struct FindIter<'a> {
+    nfa: &'a NFA,
+    haystack: &'a str,
+    kind: MatchKind,
+    /// Byte offset from which to search for the next match
+    pos: usize,
+    last_end: Option<usize>,
+}
+
+impl<'a> Iterator for FindIter<'a> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        loop {
+            if self.pos > self.haystack.len() {
+                return None;
+            }
+            let (start, end) = self.nfa.find_at(self.haystack, self.pos, self.kind)?;
+            if start == end && Some(end) == self.last_end {
+                match self.haystack[self.pos..].chars().next() {
+                    Some(c) => self.pos += c.len_utf8(),
+                    None => return None,
+                }
+                continue;
+            }
+            self.pos = end;
+            self.last_end = Some(end);
+            return Some((start, end));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::MatchKind;
+    use super::super::{DFA, NFA, Regex};
+
+    fn nfa(pattern: &str) -> NFA {
+        NFA::from_regex(&Regex::parse(pattern).unwrap())
+    }
+
+    fn find_all(pattern: &str, haystack: &str, kind: MatchKind) -> Vec<(usize, usize)> {
+        nfa(pattern).find_iter_with(haystack, kind).collect()
+    }
+
+    #[test]
+    fn test_find() {
+        assert_eq!(nfa("(a|b)*abb").find("xxababbyy"), Some((2, 7)));
+        assert_eq!(nfa("[0-9]+").find("abc 123 45"), Some((4, 7)));
+        assert_eq!(nfa("[0-9]+").find("abc"), None);
+        assert_eq!(nfa("b*").find("abc"), Some((0, 0)));
+        assert_eq!(nfa("").find(""), Some((0, 0)));
+        assert_eq!(nfa("ü+").find("aüüb"), Some((1, 5)));
+    }
+
+    #[test]
+    fn test_find_kinds() {
+        let longest = |p: &str, h: &str| nfa(p).find_at(h, 0, MatchKind::LeftmostLongest);
+        let first = |p: &str, h: &str| nfa(p).find_at(h, 0, MatchKind::LeftmostFirst);
+
+        assert_eq!(longest("a|ab", "xab"), Some((1, 3)));
+        assert_eq!(first("a|ab", "xab"), Some((1, 2)));
+        assert_eq!(first("ab|a", "xab"), Some((1, 3)));
+        assert_eq!(first("a*", "aaab"), Some((0, 3)));
+        assert_eq!(first("a?a", "aa"), Some((0, 2)));
+        assert_eq!(first("(a|ab)(c|bcd)", "abcd"), Some((0, 4)));
+        assert_eq!(longest("(a|ab)(c|bcd)", "abcd"), Some((0, 4)));
+        assert_eq!(first("(|a)b*", "ab"), Some((0, 0)));
+        assert_eq!(longest("(|a)b*", "ab"), Some((0, 2)));
+    }
+
+    #[test]
+    fn test_find_earlier_start_finishing_later() {
+        // c matches first, but the match of abcd began earlier
+        assert_eq!(nfa("abcd|c").find_at("xabcd", 0, MatchKind::LeftmostLongest), Some((1, 5)));
+        assert_eq!(nfa("abcd|c").find_at("xabcd", 0, MatchKind::LeftmostFirst), Some((1, 5)));
+        assert_eq!(nfa("abcd|c").find_at("xabce", 0, MatchKind::LeftmostFirst), Some((3, 4)));
+        assert_eq!(nfa("b|abc").find_at("abc", 1, MatchKind::LeftmostLongest), Some((1, 2)));
+    }
+
+    #[test]
+    fn test_find_no_match_linear() {
+        // restarting at every position took quadratic time here
+        let haystack = "a".repeat(4000);
+        let n = nfa("[a-z]*0");
+        assert_eq!(n.find_at(&haystack, 0, MatchKind::LeftmostLongest), None);
+        assert_eq!(n.find_at(&haystack, 0, MatchKind::LeftmostFirst), None);
+        assert_eq!(n.find(&(haystack + "0")), Some((0, 4001)));
+    }
+
+    #[test]
+    fn test_find_first_through_accepting_state() {
+        // the accepting states of an NFA built from a DFA have transitions
+        // out, which a thread stopping there would never follow
+        let n = NFA::from_dfa(&DFA::from_nfa(&nfa("a+|b")).minimize().0);
+        assert_eq!(n.find_at("xaaab", 0, MatchKind::LeftmostFirst), Some((1, 4)));
+        assert_eq!(n.find_at("xaaab", 0, MatchKind::LeftmostFirst),
+                   nfa("a+|b").find_at("xaaab", 0, MatchKind::LeftmostFirst));
+    }
+
+    #[test]
+    fn test_find_iter() {
+        assert_eq!(find_all("[0-9]+", "1 22 333", MatchKind::LeftmostLongest),
+                   vec![(0, 1), (2, 4), (5, 8)]);
+        assert_eq!(find_all("a|ab", "abab", MatchKind::LeftmostFirst),
+                   vec![(0, 1), (2, 3)]);
+        assert_eq!(find_all("a*", "baaab", MatchKind::LeftmostLongest),
+                   vec![(0, 0), (1, 4), (5, 5)]);
+        assert_eq!(find_all("x*", "ab", MatchKind::LeftmostLongest),
+                   vec![(0, 0), (1, 1), (2, 2)]);
+        assert_eq!(find_all("x", "", MatchKind::LeftmostLongest), vec![]);
+    }
+}
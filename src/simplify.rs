@@ -0,0 +1,185 @@
+use std::mem;
+
+use super::Regex;
+
+impl Regex {
+
+    /// An equivalent regex, usually smaller, with the Kleene algebra
+    /// identities `∅r = r∅ = ∅`, `εr = rε = r`, `∅|r = r|∅ = r`, `r|r = r`,
+    /// `(r*)* = r*`, `∅* = ε* = ε`, `∅&r = r&∅ = ∅`, `r&r = r` and `~~r = r`
+    /// applied from the leaves up. Empty classes become `∅`, as do groups
+    /// that can never match. Unlike `canonical`, the alternatives that remain
+    /// keep their order, so leftmost-first matches and their groups are
+    /// unchanged, except where `~~r = r` exposes the priorities of `r` that
+    /// matching through its complement's DFA had ignored.
+    pub fn simplify(&self) -> Regex {
+        match *self {
+            Regex::Nothing | Regex::Empty | Regex::Single(_) => self.clone(),
+            Regex::Class(ref k) => if k.ranges().is_empty() { Regex::Nothing } else { self.clone() },
+            Regex::Or(ref r, ref s) => {
+                let mut alternatives = vec![];
+                push_alternatives(r.simplify(), &mut alternatives);
+                push_alternatives(s.simplify(), &mut alternatives);
+                let mut kept: Vec<Regex> = vec![];
+                for r in alternatives {
+                    // a repeated alternative could only match after its first copy failed
+                    if r != Regex::Nothing && !kept.contains(&r) {
+                        kept.push(r);
+                    }
+                }
+                Regex::alternation(kept)
+            },
+            Regex::Then(ref r, ref s) => match (r.simplify(), s.simplify()) {
+                (Regex::Nothing, _) | (_, Regex::Nothing) => Regex::Nothing,
+                (Regex::Empty, s) => s,
+                (r, Regex::Empty) => r,
+                (r, s) => r.into_then(s),
+            },
+            Regex::Star(ref r) => match r.simplify() {
+                Regex::Nothing | Regex::Empty => Regex::Empty,
+                r @ Regex::Star(_) => r,
+                r => r.into_star(),
+            },
+            Regex::Capture(g, ref r) => match r.simplify() {
+                Regex::Nothing => Regex::Nothing,
+                r => Regex::Capture(g, Box::new(r)),
+            },
+            Regex::And(ref r, ref s) => match (r.simplify(), s.simplify()) {
+                (Regex::Nothing, _) | (_, Regex::Nothing) => Regex::Nothing,
+                (r, s) => if r == s { r } else { r.into_and(s) },
+            },
+            Regex::Not(ref r) => match r.simplify() {
+                Regex::Not(ref mut r) => mem::replace(r, Regex::Empty),
+                r => r.into_not(),
+            },
+        }
+    }
+}
+
+/// Pushes the operands of `r` if it is a chain of alternations, or else `r`
+fn push_alternatives(mut r: Regex, alternatives: &mut Vec<Regex>) {
+    match r {
+        Regex::Or(ref mut r, ref mut s) => {
+            push_alternatives(mem::replace(r, Regex::Empty), alternatives);
+            push_alternatives(mem::replace(s, Regex::Empty), alternatives);
+        },
+        r => alternatives.push(r),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::super::{MatchKind, NFA, Regex};
+    use super::super::rng::Rng;
+    use super::super::test::strings;
+
+    fn simplify(pattern: &str) -> Regex {
+        Regex::parse(pattern).unwrap().simplify()
+    }
+
+    /// A random regex over `a` and `b` with at most `depth` nested operators,
+    /// weighted towards the cases the identities apply to. Intersections
+    /// and complements are rarer, as each is compiled through a DFA.
+    fn random_regex(rng: &mut Rng, depth: usize) -> Regex {
+        let leaf = depth == 0 || rng.below(4) == 0;
+        if leaf {
+            return match rng.below(6) {
+                0 => Regex::Nothing,
+                1 => Regex::Empty,
+                2 => Regex::class(&[], false),
+                3 => Regex::class(&[('a', 'b')], false),
+                _ => Regex::Single(*rng.choose(&['a', 'b'])),
+            };
+        }
+        match rng.below(10) {
+            0 | 1 => random_regex(rng, depth - 1).or(&random_regex(rng, depth - 1)),
+            2 => {
+                let r = random_regex(rng, depth - 1);
+                r.or(&r)
+            },
+            3 | 4 => random_regex(rng, depth - 1).then(&random_regex(rng, depth - 1)),
+            5 => random_regex(rng, depth - 1).star(),
+            6 => random_regex(rng, depth - 1).star().star(),
+            7 => random_regex(rng, depth - 1).and(&random_regex(rng, depth - 1)),
+            8 => {
+                let r = random_regex(rng, depth - 1);
+                r.and(&r)
+            },
+            _ => {
+                let r = random_regex(rng, depth - 1);
+                if rng.below(2) == 0 { r.not() } else { r.not().not() }
+            },
+        }
+    }
+
+    /// The number of operators and leaves in `r`, and whether any of them are
+    /// intersections or complements
+    fn size(r: &Regex) -> (usize, bool) {
+        match *r {
+            Regex::Nothing | Regex::Empty | Regex::Single(_) | Regex::Class(_) => (1, false),
+            Regex::Or(ref r, ref s) | Regex::Then(ref r, ref s) => {
+                let ((a, x), (b, y)) = (size(r), size(s));
+                (a + b + 1, x || y)
+            },
+            Regex::And(ref r, ref s) => (size(r).0 + size(s).0 + 1, true),
+            Regex::Star(ref r) | Regex::Capture(_, ref r) => {
+                let (a, x) = size(r);
+                (a + 1, x)
+            },
+            Regex::Not(ref r) => (size(r).0 + 1, true),
+        }
+    }
+
+    fn random_string(rng: &mut Rng, max_len: usize) -> String {
+        let len = rng.below(max_len + 1);
+        (0..len).map(|_| *rng.choose(&['a', 'b', 'c'])).collect()
+    }
+
+    #[test]
+    fn test_simplify_identities() {
+        assert_eq!(Regex::Empty.or(&Regex::Empty).then(&Regex::Empty).simplify(), Regex::Empty);
+        assert_eq!(simplify("(?:)a(?:)"), Regex::Single('a'));
+        assert_eq!(simplify("a|a|b|a"), simplify("a|b"));
+        assert_eq!(simplify("(?:a|b)|(?:b|c)"), simplify("a|b|c"));
+        assert_eq!(simplify("(?:a*)**"), simplify("a*"));
+        assert_eq!(simplify("(?:)*"), Regex::Empty);
+        assert_eq!(simplify("[]*"), Regex::Empty);
+        assert_eq!(simplify("a[]|b"), Regex::Single('b'));
+        assert_eq!(simplify("a([])"), Regex::Nothing);
+        assert_eq!(simplify("[]|[]"), Regex::Nothing);
+        assert_eq!(simplify("(?:)|a|"), simplify("|a"));
+        assert_eq!(simplify("b|a"), Regex::parse("b|a").unwrap());
+        assert_eq!(simplify("((?:)a)"), Regex::parse("(a)").unwrap());
+    }
+
+    #[test]
+    fn test_simplify_random() {
+        let mut rng = Rng::new(18);
+        let short = strings(&['a', 'b', 'c'], 4);
+        for _ in 0..300 {
+            let r = random_regex(&mut rng, 5);
+            let s = r.simplify();
+            let (n, m) = (NFA::from_regex(&r), NFA::from_regex(&s));
+
+            let ((before, through_dfa), (after, _)) = (size(&r), size(&s));
+            assert!(after <= before, "{:?} grew to {:?}", r, s);
+            // minimized DFAs need not shrink along with the regexes they come from
+            if !through_dfa {
+                assert!(m.nodes.len() <= n.nodes.len(), "{:?} grew to {:?}", r, s);
+            }
+            assert_eq!(s.simplify(), s, "{:?}", r);
+            for x in short.iter() {
+                assert_eq!(n.accepts(x), m.accepts(x), "{:?} and {:?} on {:?}", r, s, x);
+            }
+            for _ in 0..20 {
+                let x = random_string(&mut rng, 12);
+                let xs: Vec<char> = x.chars().collect();
+                assert_eq!(n.accepts(&xs), m.accepts(&xs), "{:?} and {:?} on {:?}", r, s, x);
+                // intersections and complements match through DFAs, which have no priorities
+                let kind = if through_dfa { MatchKind::LeftmostLongest } else { MatchKind::LeftmostFirst };
+                assert_eq!(n.find_at(&x, 0, kind), m.find_at(&x, 0, kind), "{:?} and {:?} on {:?}", r, s, x);
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::slice;
+
+/// A set of integers below a fixed capacity with constant time insertion,
+/// membership and clearing, which iterates in insertion order. This is the
+/// representation from Briggs and Torczon's "An Efficient Representation for
+/// Sparse Sets": `dense` lists the members and `sparse` maps each member to
+/// its position in `dense`, with stale entries detected by checking they
+/// point back at themselves.
+#[derive(Debug,Clone)]
+pub struct SparseSet {
+    dense: Vec<usize>,
+    sparse: Vec<usize>,
+}
+
+impl SparseSet {
+
+    pub fn new(capacity: usize) -> SparseSet {
+        SparseSet {
+            dense: Vec::with_capacity(capacity),
+            sparse: vec![0; capacity],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    pub fn contains(&self, x: usize) -> bool {
+        let i = self.sparse[x];
+        i < self.dense.len() && self.dense[i] == x
+    }
+
+    /// Returns whether `x` was newly inserted
+    pub fn insert(&mut self, x: usize) -> bool {
+        if self.contains(x) {
+            return false;
+        }
+        self.sparse[x] = self.dense.len();
+        self.dense.push(x);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.dense.clear();
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, usize> {
+        self.dense.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::SparseSet;
+
+    #[test]
+    fn test_sparse_set() {
+        let mut s = SparseSet::new(10);
+        assert!(s.is_empty());
+
+        assert!(s.insert(7));
+        assert!(s.insert(2));
+        assert!(!s.insert(7));
+        assert!(s.contains(2));
+        assert!(!s.contains(3));
+        assert_eq!(s.iter().cloned().collect::<Vec<usize>>(), vec![7, 2]);
+
+        s.clear();
+        assert!(s.is_empty());
+        assert!(!s.contains(7));
+        assert!(s.insert(2));
+        assert_eq!(s.iter().cloned().collect::<Vec<usize>>(), vec![2]);
+    }
+}